@@ -0,0 +1,86 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use fcrlf::{convert_stream, scan_delims, Delim, Encoding, FileContents, Options};
+
+/// Makes `n` lines of source-like text, choosing each delimiter by `delim_of(i)`.
+fn lines(n: usize, delim_of: impl Fn(usize) -> &'static str) -> Vec<u8> {
+    let mut out = String::new();
+    for i in 0..n {
+        out.push_str("    let value = compute(index, \"some text\"); // comment ");
+        out.push_str(&i.to_string());
+        out.push_str(delim_of(i));
+    }
+    out.into_bytes()
+}
+
+fn inputs() -> Vec<(&'static str, Vec<u8>)> {
+    let mixed = |i: usize| match i % 3 {
+        0 => "\n",
+        1 => "\r\n",
+        _ => "\r",
+    };
+    vec![
+        ("lf", lines(10_000, |_| "\n")),
+        ("crlf", lines(10_000, |_| "\r\n")),
+        ("mixed", lines(10_000, mixed)),
+        (
+            "large",
+            lines(200_000, |i| if i % 100 == 0 { "\r\n" } else { "\n" }),
+        ),
+    ]
+}
+
+fn utf16le(utf8: &[u8]) -> Vec<u8> {
+    let mut out = vec![0xFF, 0xFE];
+    out.extend(Encoding::Utf16Le.encode_str(std::str::from_utf8(utf8).unwrap()));
+    out
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, raw) in inputs() {
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+        group.bench_with_input(BenchmarkId::new("utf8", name), &raw, |b, raw| {
+            b.iter(|| FileContents::from_bytes(black_box(raw)))
+        });
+        let raw = utf16le(&raw);
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+        group.bench_with_input(BenchmarkId::new("utf16", name), &raw, |b, raw| {
+            b.iter(|| FileContents::from_bytes(black_box(raw)))
+        });
+    }
+    group.finish();
+}
+
+fn convert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("convert");
+    for (name, raw) in inputs() {
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+        group.bench_with_input(BenchmarkId::new("write_to", name), &raw, |b, raw| {
+            let contents = FileContents::from_bytes(raw);
+            b.iter(|| {
+                let mut out = Vec::with_capacity(raw.len());
+                contents
+                    .write_to(&mut out, Delim::LF, &Options::default())
+                    .unwrap();
+                out
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("stream", name), &raw, |b, raw| {
+            b.iter(|| {
+                let mut out = Vec::with_capacity(raw.len());
+                convert_stream(&raw[..], &mut out, Delim::LF, &Options::default()).unwrap();
+                out
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("scan_delims", name), &raw, |b, raw| {
+            b.iter(|| scan_delims(black_box(raw)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, convert);
+criterion_main!(benches);
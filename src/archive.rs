@@ -0,0 +1,128 @@
+//! Conversion of text entries in zip archives, for `--zip`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::read;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use zip::{ZipArchive, ZipWriter};
+
+use fcrlf::{is_binary, Encoding, Ending, FileContents, Scan};
+
+use crate::{check_writable, warn, write_file, Config, FileStamp, Outcome, PathFmt};
+
+/// Converts or detects entries of a zip archive, which is loaded at once.
+///
+/// Entries which are directories, binary, or not matched by `--zip-entries` are copied as they are,
+/// keeping their compressed data. Converted entries keep their compression method, modified time
+/// and permissions. The outcome is of all text entries together, and diffs are shown for each.
+/// `stamp` is of the archive before it was read.
+pub fn convert_zip_file(p: &Path, config: &Config, stamp: FileStamp) -> Result<Outcome> {
+    let filter = config
+        .zip
+        .as_ref()
+        .expect("zip archives are converted only with --zip");
+    let raw = read(p).with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+    let mut archive = ZipArchive::new(Cursor::new(&raw[..]))
+        .with_context(|| format!("reading zip archive {}", PathFmt(p)))?;
+
+    // Delimiters of entries aren't reported one by one, so the ending is not either.
+    let mut scan = Scan {
+        delim_types: BTreeSet::new(),
+        delim_counts: BTreeMap::new(),
+        has_bom: false,
+        ending: Ending::Empty,
+        trailing_whitespace: false,
+        tabs: false,
+    };
+    // Converted contents of each entry, or `None` for ones copied as they are.
+    let mut converted: Vec<Option<Vec<u8>>> = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .with_context(|| format!("reading entry {} of {}", i, PathFmt(p)))?;
+        let name = entry.name().unwrap_or_default().into_owned();
+        if entry.is_dir() || !filter.is_match(Path::new(&name)) {
+            converted.push(None);
+            continue;
+        }
+        let mut contents = Vec::new();
+        if let Err(e) = entry.read_to_end(&mut contents) {
+            warn(format_args!(
+                "can't read entry {} of {}, leaving it: {}",
+                name,
+                PathFmt(p),
+                e
+            ));
+            converted.push(None);
+            continue;
+        }
+
+        let encoding = config
+            .encoding
+            .unwrap_or_else(|| Encoding::detect(&contents));
+        if (!config.process_binary && is_binary(&contents, encoding))
+            || config.has_marker(&contents, encoding)
+        {
+            config.log(1, || format!("skipping {} in {}", name, PathFmt(p)));
+            converted.push(None);
+            continue;
+        }
+
+        let file_contents = FileContents::parse(&contents, encoding, &config.split);
+        let entry_scan = file_contents.scan_with(&config.options);
+        scan.delim_types
+            .extend(entry_scan.delim_types.iter().copied());
+        for (&d, &n) in &entry_scan.delim_counts {
+            *scan.delim_counts.entry(d).or_insert(0) += n;
+        }
+        if !config.contents_need_conversion(&file_contents, &entry_scan) {
+            converted.push(None);
+            continue;
+        }
+
+        let target = config.target_for(&entry_scan);
+        let options = config.options_for(&entry_scan);
+        if config.show_diff && !config.quiet {
+            let entry_name = format!("{}/{}", PathFmt(p), name);
+            print!(
+                "{}",
+                config.diff(&entry_name, &file_contents, target, &options)?
+            );
+        }
+        config.log(2, || format!("converting {} in {}", name, PathFmt(p)));
+        let mut out = Vec::with_capacity(contents.len());
+        file_contents.write_to(&mut out, target, &options)?;
+        converted.push(Some(out));
+    }
+
+    let needs_conversion = converted.iter().any(Option::is_some);
+    if needs_conversion && config.do_covert && !config.confirm(&PathFmt(p), &scan)? {
+        return Ok(Outcome::Declined);
+    }
+    if needs_conversion && config.do_covert {
+        write_file(p, config, stamp, None, |w| {
+            let mut zip = ZipWriter::new(&mut *w);
+            zip.set_raw_comment(archive.comment().into())?;
+            for (i, contents) in converted.iter().enumerate() {
+                match contents {
+                    Some(contents) => {
+                        let entry = archive.by_index_raw(i)?;
+                        let (name, options) = (entry.name()?.into_owned(), entry.options());
+                        drop(entry);
+                        zip.start_file(name, options)?;
+                        zip.write_all(contents)?;
+                    }
+                    None => zip.raw_copy_file(archive.by_index_raw(i)?)?,
+                }
+            }
+            zip.finish()?;
+            Ok(())
+        })?;
+    } else if needs_conversion && config.check_writable {
+        check_writable(p, config)?;
+    }
+
+    Ok(config.outcome(&PathFmt(p), scan, None, needs_conversion, false))
+}
@@ -0,0 +1,1388 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use memchr::{memchr2, memchr3};
+
+use crate::delim::{Delim, Encoding};
+use crate::error::Result;
+use crate::UTF8_BOM;
+
+/// Contents of a file, split into lines.
+#[derive(Debug, PartialEq)]
+pub struct FileContents {
+    pub encoding: Encoding,
+    /// Whether contents start with UTF-8 BOM, which is not included in `lines`.
+    /// BOM of UTF-16 is kept in the first line.
+    pub bom: bool,
+    /// Lines of contents. Only the last line has no delimiter, and it may be empty.
+    pub lines: Vec<Line>,
+}
+
+impl FileContents {
+    /// Parses contents, detecting UTF-16 by its BOM.
+    ///
+    /// CRLF, LF and CR are recognized as delimiters.
+    pub fn from_bytes(bytes: &[u8]) -> FileContents {
+        FileContents::from_bytes_with(bytes, Encoding::detect(bytes))
+    }
+
+    /// Parses contents in `encoding`.
+    pub fn from_bytes_with(bytes: &[u8], encoding: Encoding) -> FileContents {
+        FileContents::parse(bytes, encoding, &SplitOptions::default())
+    }
+
+    /// Parses contents in `encoding`, recognizing delimiters selected by `split`.
+    pub fn parse(mut bytes: &[u8], encoding: Encoding, split: &SplitOptions) -> FileContents {
+        let bom = encoding == Encoding::Utf8 && bytes.starts_with(UTF8_BOM);
+        if bom {
+            bytes = &bytes[UTF8_BOM.len()..];
+        }
+
+        let lines = match encoding {
+            Encoding::Utf8 => FileContents::split_bytes(bytes, split),
+            Encoding::Utf16Le => FileContents::split_utf16(bytes, u16::from_le_bytes, split),
+            Encoding::Utf16Be => FileContents::split_utf16(bytes, u16::from_be_bytes, split),
+        };
+        FileContents {
+            encoding,
+            bom,
+            lines,
+        }
+    }
+
+    fn split_bytes(bytes: &[u8], split: &SplitOptions) -> Vec<Line> {
+        LineIter::with_options(bytes, *split)
+            .map(|(text, line_end)| Line {
+                text: text.to_vec(),
+                line_end,
+            })
+            .collect()
+    }
+
+    /// Splits at CR and LF code units. Odd trailing byte is kept as text.
+    fn split_utf16(
+        mut bytes: &[u8],
+        to_unit: fn([u8; 2]) -> u16,
+        split: &SplitOptions,
+    ) -> Vec<Line> {
+        const CR: u16 = 0x000D;
+        const LF: u16 = 0x000A;
+        const LS: u16 = 0x2028;
+        const PS: u16 = 0x2029;
+        const NEL: u16 = 0x0085;
+
+        let unit_at =
+            |bytes: &[u8], i: usize| bytes.get(2 * i..2 * i + 2).map(|u| to_unit([u[0], u[1]]));
+        let is_delim = |unit: u16| {
+            unit == CR
+                || unit == LF
+                || (split.unicode_lineseps && (unit == LS || unit == PS))
+                || (split.nel && unit == NEL)
+        };
+
+        let mut lines = Vec::new();
+        let mut cur_line = Line::new();
+
+        while let Some(unit) = unit_at(bytes, 0) {
+            if !is_delim(unit) {
+                // Text up to the next delimiter is copied at once.
+                let n = bytes
+                    .chunks_exact(2)
+                    .position(|u| is_delim(to_unit([u[0], u[1]])))
+                    .unwrap_or(bytes.len() / 2);
+                cur_line.text.extend_from_slice(&bytes[..2 * n]);
+                bytes = &bytes[2 * n..];
+            } else if unit == CR && unit_at(bytes, 1) == Some(LF) {
+                cur_line.line_end = Some(Delim::CRLF);
+                bytes = &bytes[4..];
+                lines.push(cur_line);
+                cur_line = Line::new();
+            } else if unit == CR && split.trailing_cr_as_text && unit_at(bytes, 1).is_none() {
+                cur_line.text.extend_from_slice(&bytes[..2]);
+                bytes = &bytes[2..];
+            } else if unit == LF {
+                cur_line.line_end = Some(Delim::LF);
+                bytes = &bytes[2..];
+                lines.push(cur_line);
+                cur_line = Line::new();
+            } else if unit == CR {
+                cur_line.line_end = Some(Delim::CR);
+                bytes = &bytes[2..];
+                lines.push(cur_line);
+                cur_line = Line::new();
+            } else {
+                cur_line.line_end = Some(match unit {
+                    LS => Delim::LS,
+                    PS => Delim::PS,
+                    _ => Delim::NEL,
+                });
+                bytes = &bytes[2..];
+                lines.push(cur_line);
+                cur_line = Line::new();
+            }
+        }
+
+        cur_line.text.extend_from_slice(bytes);
+        lines.push(cur_line);
+        lines
+    }
+
+    /// Returns delimiter types used in contents.
+    pub fn delim_types(&self) -> BTreeSet<Delim> {
+        let mut types = BTreeSet::new();
+
+        for l in &self.lines {
+            if let Some(d) = l.line_end {
+                types.insert(d);
+            }
+        }
+
+        types
+    }
+
+    /// Returns the number of each delimiter in contents.
+    pub fn delim_counts(&self) -> BTreeMap<Delim, usize> {
+        let mut counts = BTreeMap::new();
+
+        for l in &self.lines {
+            if let Some(d) = l.line_end {
+                *counts.entry(d).or_insert(0) += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// Finds the first delimiter for which `pred` returns true.
+    pub fn find_delim(&self, pred: impl Fn(Delim) -> bool) -> Option<DelimPosition> {
+        let mut offset = if self.bom { UTF8_BOM.len() } else { 0 };
+
+        for (i, l) in self.lines.iter().enumerate() {
+            offset += l.text.len();
+            let d = l.line_end?;
+            if pred(d) {
+                return Some(DelimPosition {
+                    delim: d,
+                    offset,
+                    line: i + 1,
+                });
+            }
+            offset += d.encoded_len(self.encoding);
+        }
+
+        None
+    }
+
+    pub fn scan(&self) -> Scan {
+        self.scan_with(&Options::default())
+    }
+
+    /// Scans contents, ignoring delimiters which are kept by `options.regions`.
+    pub fn scan_with(&self, options: &Options) -> Scan {
+        let mut tracker = options
+            .regions
+            .as_ref()
+            .map(|regions| RegionTracker::new(regions, self.encoding));
+        let targets = self.delim_bytes_lines(options);
+        let mut delim_types = BTreeSet::new();
+        let mut delim_counts = BTreeMap::new();
+        for (i, l) in self.lines.iter().enumerate() {
+            let kept = tracker.as_mut().is_some_and(|t| t.keeps(l)) || targets[i];
+            if let (Some(d), false) = (l.line_end, kept) {
+                delim_types.insert(d);
+                *delim_counts.entry(d).or_insert(0) += 1;
+            }
+        }
+
+        let trailing_whitespace = self
+            .lines
+            .iter()
+            .any(|l| trailing_whitespace_len(&l.text, self.encoding) > 0);
+        let tabs = options.replace_tabs.is_some()
+            && self
+                .lines
+                .iter()
+                .any(|l| has_tabs(&l.text, options.all_tabs, self.encoding));
+
+        let last = self.lines.last().expect("lines has last line");
+        let ending = match () {
+            () if !last.text.is_empty() => Ending::Text,
+            () if self.lines.len() > 1 => Ending::Delim,
+            _ => Ending::Empty,
+        };
+
+        Scan {
+            delim_types,
+            delim_counts,
+            has_bom: self.bom,
+            ending,
+            trailing_whitespace,
+            tabs,
+        }
+    }
+
+    /// Writes contents with all delimiters replaced by `delim`.
+    ///
+    /// Text between delimiters is written as it is, unless `options` changes it.
+    pub fn write_to(&self, w: &mut impl Write, delim: Delim, options: &Options) -> Result<()> {
+        if self.bom && !options.strip_bom {
+            w.write_all(UTF8_BOM)?;
+        }
+        if options.final_newline_only {
+            let mut writer = FinalNewlineWriter::default();
+            for l in &self.lines {
+                writer.write_line(w, l, options, self.encoding)?;
+            }
+            return writer.finish(w, delim, options, self.encoding);
+        }
+
+        let mut tracker = options
+            .regions
+            .as_ref()
+            .map(|regions| RegionTracker::new(regions, self.encoding));
+        let targets = self.delim_bytes_lines(options);
+        for (i, l) in self.lines.iter().enumerate() {
+            let keep = tracker.as_mut().is_some_and(|t| t.keeps(l)) || targets[i];
+            options.write_line(w, l, self.lines.get(i + 1), delim, self.encoding, keep)?;
+        }
+        Ok(())
+    }
+
+    /// Marks lines whose delimiters are in an occurrence of `options.delim_bytes`, which is left as
+    /// one target delimiter, so converting contents again changes nothing.
+    /// Occurrences splitting a delimiter, like the CR of CRLF, are not counted.
+    fn delim_bytes_lines(&self, options: &Options) -> Vec<bool> {
+        let mut marked = vec![false; self.lines.len()];
+        let bytes = match &options.delim_bytes {
+            Some(bytes) if !bytes.is_empty() => bytes,
+            _ => return marked,
+        };
+
+        let mut raw = Vec::new();
+        let mut spans = Vec::with_capacity(self.lines.len());
+        for l in &self.lines {
+            raw.extend_from_slice(&l.text);
+            let start = raw.len();
+            if let Some(d) = l.line_end {
+                d.write_to(&mut raw, self.encoding)
+                    .expect("writing to Vec never fails");
+            }
+            spans.push((start, raw.len()));
+        }
+
+        let mut first = 0;
+        let mut start = 0;
+        while start + bytes.len() <= raw.len() {
+            let end = start + bytes.len();
+            if !raw[start..].starts_with(bytes) {
+                start += 1;
+                continue;
+            }
+            // The last line has an empty span at the end, so this stops within the lines.
+            while spans[first].1 <= start && first + 1 < spans.len() {
+                first += 1;
+            }
+            let overlapping = (first..spans.len())
+                .take_while(|&j| spans[j].0 < end)
+                .filter(|&j| spans[j].0 < spans[j].1);
+            if overlapping
+                .clone()
+                .all(|j| start <= spans[j].0 && spans[j].1 <= end)
+            {
+                for j in overlapping {
+                    marked[j] = true;
+                }
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+        marked
+    }
+
+    /// Checks whether contents end with exactly one `delim` after the last text, or are empty.
+    /// Conversion with `Options::final_newline_only` changes nothing else unless other options do.
+    pub fn has_single_final_delim(&self, delim: Delim) -> bool {
+        match self.lines.iter().rposition(|l| !l.text.is_empty()) {
+            Some(i) => self.lines[i].line_end == Some(delim) && i + 2 == self.lines.len(),
+            None => self.lines.len() == 1,
+        }
+    }
+}
+
+/// Iterator over lines of UTF-8 contents and their delimiters, borrowing the contents.
+///
+/// Lines are split in the same way as `FileContents::from_bytes`, without BOM handling.
+/// The last line has no delimiter, and it may be empty.
+#[derive(Debug, Clone)]
+pub struct LineIter<'a> {
+    rest: &'a [u8],
+    split: SplitOptions,
+    finished: bool,
+}
+
+impl<'a> LineIter<'a> {
+    const LS: &'static [u8] = b"\xE2\x80\xA8";
+    const PS: &'static [u8] = b"\xE2\x80\xA9";
+    const NEL: &'static [u8] = b"\xC2\x85";
+
+    pub fn new(bytes: &'a [u8]) -> LineIter<'a> {
+        LineIter::with_options(bytes, SplitOptions::default())
+    }
+
+    pub fn with_options(bytes: &'a [u8], split: SplitOptions) -> LineIter<'a> {
+        LineIter {
+            rest: bytes,
+            split,
+            finished: false,
+        }
+    }
+
+    /// Finds the first delimiter in `bytes`, returning its position, type and length.
+    fn find_delim(&self, bytes: &[u8]) -> Option<(usize, Delim, usize)> {
+        let mut start = 0;
+        loop {
+            // The first bytes of LS, PS and NEL are also searched for only if they are recognized.
+            let rest = &bytes[start..];
+            let i = start
+                + match (self.split.unicode_lineseps, self.split.nel) {
+                    (false, false) => memchr2(b'\r', b'\n', rest)?,
+                    (true, false) => memchr3(b'\r', b'\n', 0xE2, rest)?,
+                    (false, true) => memchr3(b'\r', b'\n', 0xC2, rest)?,
+                    (true, true) => rest
+                        .iter()
+                        .position(|&b| matches!(b, b'\r' | b'\n' | 0xE2 | 0xC2))?,
+                };
+            match bytes[i] {
+                b'\n' => return Some((i, Delim::LF, 1)),
+                b'\r' if bytes.get(i + 1) == Some(&b'\n') => return Some((i, Delim::CRLF, 2)),
+                b'\r' if self.split.trailing_cr_as_text && i + 1 == bytes.len() => return None,
+                b'\r' => return Some((i, Delim::CR, 1)),
+                _ if bytes[i..].starts_with(Self::LS) => return Some((i, Delim::LS, 3)),
+                _ if bytes[i..].starts_with(Self::PS) => return Some((i, Delim::PS, 3)),
+                _ if bytes[i..].starts_with(Self::NEL) => return Some((i, Delim::NEL, 2)),
+                _ => start = i + 1,
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for LineIter<'a> {
+    type Item = (&'a [u8], Option<Delim>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let rest = self.rest;
+        match self.find_delim(rest) {
+            Some((i, d, len)) => {
+                self.rest = &rest[i + len..];
+                Some((&rest[..i], Some(d)))
+            }
+            None => {
+                self.finished = true;
+                self.rest = &[];
+                Some((rest, None))
+            }
+        }
+    }
+}
+
+/// Options on which delimiters are recognized, in addition to or instead of CRLF, LF and CR.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SplitOptions {
+    /// Recognizes U+2028 LINE SEPARATOR and U+2029 PARAGRAPH SEPARATOR.
+    pub unicode_lineseps: bool,
+    /// Recognizes U+0085 NEXT LINE, used by EBCDIC-origin text.
+    pub nel: bool,
+    /// Treats a lone CR at the very end of contents as text instead of a delimiter.
+    pub trailing_cr_as_text: bool,
+}
+
+/// Options of conversion other than the target delimiter.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Removes UTF-8 BOM.
+    pub strip_bom: bool,
+    pub final_newline: FinalNewline,
+    /// Removes spaces and tabs at the end of lines.
+    pub trim_trailing_whitespace: bool,
+    /// Expands tabs in indentation to spaces, with tab stops at every this many columns if given.
+    pub replace_tabs: Option<usize>,
+    /// With `replace_tabs`, expands all tabs instead of only ones in indentation.
+    pub all_tabs: bool,
+    /// Converts only delimiters of this type if given, leaving others as they are.
+    pub from: Option<Delim>,
+    /// Converts only delimiters of these types if not empty, like `from` for several types.
+    pub forbidden: BTreeSet<Delim>,
+    /// Writes this byte sequence instead of the target delimiter if given.
+    /// It's written as it is, regardless of encoding.
+    pub delim_bytes: Option<Vec<u8>>,
+    /// Keeps delimiters as they are in regions between these markers if given.
+    pub regions: Option<Regions>,
+    /// Converts only the delimiter after the last text, ensuring exactly one there,
+    /// and keeps all others. `final_newline`, `from`, `forbidden` and `regions` are ignored.
+    pub final_newline_only: bool,
+}
+
+impl Options {
+    /// Writes converted `line`, where `next` is the following line if any.
+    /// With `keep`, its delimiter is written as it is.
+    pub(crate) fn write_line(
+        &self,
+        w: &mut impl Write,
+        line: &Line,
+        next: Option<&Line>,
+        delim: Delim,
+        encoding: Encoding,
+        keep: bool,
+    ) -> Result<()> {
+        self.write_text(w, line, encoding)?;
+        match self.line_end(line, next, keep) {
+            Some(LineEnd::Keep(d)) => d.write_to(w, encoding)?,
+            Some(LineEnd::Target) => self.write_target(w, delim, encoding)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Writes text of `line` without its delimiter.
+    fn write_text(&self, w: &mut impl Write, line: &Line, encoding: Encoding) -> Result<()> {
+        let mut text = &line.text[..];
+        if self.trim_trailing_whitespace {
+            text = &text[..text.len() - trailing_whitespace_len(text, encoding)];
+        }
+        match self.replace_tabs {
+            Some(width) => write_expanded(w, text, width, self.all_tabs, encoding)?,
+            None => w.write_all(text)?,
+        }
+        Ok(())
+    }
+
+    /// Writes the target delimiter, or `delim_bytes` instead if given.
+    fn write_target(&self, w: &mut impl Write, delim: Delim, encoding: Encoding) -> Result<()> {
+        match &self.delim_bytes {
+            Some(bytes) => w.write_all(bytes)?,
+            None => delim.write_to(w, encoding)?,
+        }
+        Ok(())
+    }
+
+    /// Decides the delimiter written after `line`, where `next` is the following line if any.
+    fn line_end(&self, line: &Line, next: Option<&Line>, keep: bool) -> Option<LineEnd> {
+        match (line.line_end, self.final_newline) {
+            (Some(_), FinalNewline::Remove) if next.is_some_and(Line::is_empty_last) => None,
+            (Some(d), _) if keep => Some(LineEnd::Keep(d)),
+            (Some(d), _) if self.from.is_some_and(|from| from != d) => Some(LineEnd::Keep(d)),
+            (Some(d), _) if !self.forbidden.is_empty() && !self.forbidden.contains(&d) => {
+                Some(LineEnd::Keep(d))
+            }
+            (Some(_), _) => Some(LineEnd::Target),
+            (None, FinalNewline::Ensure) if !line.text.is_empty() => Some(LineEnd::Target),
+            (None, _) => None,
+        }
+    }
+}
+
+/// Markers of regions in which delimiters are kept, like `crlf-start` and `crlf-end`.
+///
+/// A line containing `start` begins a region, and one containing `end` closes it.
+/// Delimiters of lines from the start marker up to just before the end marker are kept,
+/// so the end marker line itself ends with the target delimiter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Regions {
+    pub start: String,
+    pub end: String,
+}
+
+/// Tracks whether lines are in a region while iterating over them in order.
+#[derive(Debug)]
+pub(crate) struct RegionTracker {
+    /// Markers encoded in the encoding of contents.
+    start: Vec<u8>,
+    end: Vec<u8>,
+    in_region: bool,
+}
+
+impl RegionTracker {
+    pub(crate) fn new(regions: &Regions, encoding: Encoding) -> RegionTracker {
+        RegionTracker {
+            start: encoding.encode_str(&regions.start),
+            end: encoding.encode_str(&regions.end),
+            in_region: false,
+        }
+    }
+
+    /// Returns whether the delimiter of `line` is kept. Lines must be given in order.
+    pub(crate) fn keeps(&mut self, line: &Line) -> bool {
+        let contains = |marker: &[u8]| {
+            !marker.is_empty() && line.text.windows(marker.len()).any(|w| w == marker)
+        };
+        if self.in_region {
+            self.in_region = !contains(&self.end);
+        } else {
+            self.in_region = contains(&self.start);
+        }
+        self.in_region
+    }
+}
+
+/// Writes lines for `Options::final_newline_only`, in order.
+///
+/// Delimiters after the last text seen are held, and written as they are only if more text follows.
+/// Otherwise, they are replaced by a single target delimiter in `finish`.
+#[derive(Debug, Default)]
+pub(crate) struct FinalNewlineWriter {
+    pending: Vec<Delim>,
+    has_text: bool,
+}
+
+impl FinalNewlineWriter {
+    pub(crate) fn write_line(
+        &mut self,
+        w: &mut impl Write,
+        line: &Line,
+        options: &Options,
+        encoding: Encoding,
+    ) -> Result<()> {
+        if !line.text.is_empty() {
+            for d in self.pending.drain(..) {
+                d.write_to(w, encoding)?;
+            }
+            options.write_text(w, line, encoding)?;
+            self.has_text = true;
+        }
+        self.pending.extend(line.line_end);
+        Ok(())
+    }
+
+    /// Writes the final delimiter, unless contents have no text.
+    pub(crate) fn finish(
+        self,
+        w: &mut impl Write,
+        delim: Delim,
+        options: &Options,
+        encoding: Encoding,
+    ) -> Result<()> {
+        if self.has_text {
+            options.write_target(w, delim, encoding)?;
+        }
+        Ok(())
+    }
+}
+
+/// Delimiter written after a line.
+#[derive(Debug, Clone, Copy)]
+enum LineEnd {
+    /// The original delimiter, left as it is.
+    Keep(Delim),
+    /// The target delimiter.
+    Target,
+}
+
+/// Treatment of the delimiter at the end of contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FinalNewline {
+    /// Keeps the delimiter at the end, or its absence.
+    #[default]
+    Keep,
+    /// Appends the delimiter to non-empty contents without it.
+    Ensure,
+    /// Removes one delimiter at the end.
+    Remove,
+}
+
+/// Properties of contents which decide whether conversion is needed.
+/// The default is of empty contents.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Scan {
+    pub delim_types: BTreeSet<Delim>,
+    /// Number of each delimiter seen, which is complete only when the whole contents are scanned.
+    pub delim_counts: BTreeMap<Delim, usize>,
+    /// Whether contents start with UTF-8 BOM.
+    pub has_bom: bool,
+    pub ending: Ending,
+    /// Whether any line ends with spaces or tabs.
+    pub trailing_whitespace: bool,
+    /// Whether any line has tabs which `Options::replace_tabs` expands.
+    pub tabs: bool,
+}
+
+impl Scan {
+    /// Preference of delimiters on ties in `dominant_delim`.
+    const PREFERENCE: [Delim; 6] = [
+        Delim::LF,
+        Delim::CRLF,
+        Delim::CR,
+        Delim::LS,
+        Delim::PS,
+        Delim::NEL,
+    ];
+
+    /// Returns the most common delimiter, or `None` if there are no delimiters.
+    /// Ties are broken in the order of LF, CRLF, CR, LS, PS and NEL.
+    pub fn dominant_delim(&self) -> Option<Delim> {
+        let mut dominant = None;
+        let mut max = 0;
+        for d in &Scan::PREFERENCE {
+            let n = self.delim_counts.get(d).copied().unwrap_or(0);
+            if n > max {
+                dominant = Some(*d);
+                max = n;
+            }
+        }
+        dominant
+    }
+}
+
+/// Counts bytes of spaces and tabs at the end of `text`.
+pub(crate) fn trailing_whitespace_len(text: &[u8], encoding: Encoding) -> usize {
+    let is_whitespace = |u: u16| u == 0x0020 || u == 0x0009;
+    let units = |to_unit: fn([u8; 2]) -> u16| {
+        // Odd trailing byte is not a code unit, so nothing is trimmed.
+        if !text.len().is_multiple_of(2) {
+            return 0;
+        }
+        2 * text
+            .chunks_exact(2)
+            .rev()
+            .take_while(|u| is_whitespace(to_unit([u[0], u[1]])))
+            .count()
+    };
+
+    match encoding {
+        Encoding::Utf8 => text
+            .iter()
+            .rev()
+            .take_while(|&&b| is_whitespace(b.into()))
+            .count(),
+        Encoding::Utf16Le => units(u16::from_le_bytes),
+        Encoding::Utf16Be => units(u16::from_be_bytes),
+    }
+}
+
+/// Iterates over code units of `text`, which are bytes in UTF-8.
+/// Odd trailing byte of UTF-16 is not a code unit, so it's not included.
+fn code_units(text: &[u8], encoding: Encoding) -> impl Iterator<Item = u16> + '_ {
+    let unit_len = if encoding == Encoding::Utf8 { 1 } else { 2 };
+    text.chunks_exact(unit_len).map(move |u| match encoding {
+        Encoding::Utf8 => u[0].into(),
+        Encoding::Utf16Le => u16::from_le_bytes([u[0], u[1]]),
+        Encoding::Utf16Be => u16::from_be_bytes([u[0], u[1]]),
+    })
+}
+
+/// Checks whether `text` has tabs in its indentation, or anywhere with `all`.
+pub(crate) fn has_tabs(text: &[u8], all: bool, encoding: Encoding) -> bool {
+    for u in code_units(text, encoding) {
+        match u {
+            0x0009 => return true,
+            0x0020 => {}
+            _ if !all => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Writes `text` with tabs in its indentation, or all tabs with `all`, expanded to spaces up to
+/// the next multiple of `width` columns. Other bytes are written as they are.
+///
+/// Each code point takes a column, so wide characters are not considered. Tabs are removed with a
+/// `width` of 0.
+fn write_expanded(
+    w: &mut impl Write,
+    text: &[u8],
+    width: usize,
+    all: bool,
+    encoding: Encoding,
+) -> io::Result<()> {
+    let unit_len = if encoding == Encoding::Utf8 { 1 } else { 2 };
+    let space = encoding.encode_str(" ");
+    // Continuation bytes of UTF-8 and low surrogates of UTF-16 don't start code points.
+    let starts_code_point = |u: u16| match encoding {
+        Encoding::Utf8 => u & 0xC0 != 0x80,
+        Encoding::Utf16Le | Encoding::Utf16Be => !(0xDC00..0xE000).contains(&u),
+    };
+
+    let mut column = 0;
+    let mut written = 0;
+    for (i, u) in code_units(text, encoding).enumerate() {
+        if u == 0x0009 {
+            w.write_all(&text[written..i * unit_len])?;
+            let n = if width == 0 {
+                0
+            } else {
+                width - column % width
+            };
+            for _ in 0..n {
+                w.write_all(&space)?;
+            }
+            column += n;
+            written = (i + 1) * unit_len;
+        } else if !all && u != 0x0020 {
+            break;
+        } else if starts_code_point(u) {
+            column += 1;
+        }
+    }
+    w.write_all(&text[written..])
+}
+
+/// Position of a delimiter in contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelimPosition {
+    pub delim: Delim,
+    /// Byte offset of the delimiter from the start of contents, including BOM.
+    pub offset: usize,
+    /// Line number which the delimiter ends, starting from 1.
+    pub line: usize,
+}
+
+/// How contents end, excluding BOM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ending {
+    /// Contents are empty.
+    #[default]
+    Empty,
+    /// Contents end with a delimiter.
+    Delim,
+    /// Contents end with text without a delimiter.
+    Text,
+}
+
+/// A line and its delimiter.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Line {
+    /// Text without the delimiter.
+    pub text: Vec<u8>,
+    /// Delimiter, or `None` for the last line.
+    pub line_end: Option<Delim>,
+}
+
+impl Line {
+    pub fn new() -> Line {
+        Line {
+            text: Vec::new(),
+            line_end: None,
+        }
+    }
+
+    /// Checks whether this is the last line of contents ending with a delimiter.
+    pub fn is_empty_last(&self) -> bool {
+        self.text.is_empty() && self.line_end.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn parse_file() {
+        let raw = b"abc\r\ndef\nghi\rj";
+        let parsed = FileContents::from_bytes(raw);
+
+        assert_eq!(
+            parsed,
+            FileContents {
+                encoding: Encoding::Utf8,
+                bom: false,
+                lines: vec![
+                    Line {
+                        text: b"abc".to_vec(),
+                        line_end: Some(Delim::CRLF)
+                    },
+                    Line {
+                        text: b"def".to_vec(),
+                        line_end: Some(Delim::LF)
+                    },
+                    Line {
+                        text: b"ghi".to_vec(),
+                        line_end: Some(Delim::CR)
+                    },
+                    Line {
+                        text: b"j".to_vec(),
+                        line_end: None
+                    },
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn parse_write_round_trip() {
+        let raw = b"abc\r\ndef\nghi\rj";
+        let parsed = FileContents::from_bytes(raw);
+        let mut written = Vec::<u8>::new();
+        parsed
+            .write_to(&mut written, Delim::CRLF, &Options::default())
+            .unwrap();
+        assert_eq!(&written, b"abc\r\ndef\r\nghi\r\nj");
+    }
+
+    #[test]
+    fn parse_write_utf16() {
+        let le = b"\xFF\xFEa\0\r\0\n\0b\0\n\0\x0D\x0A\r\0";
+        let parsed = FileContents::from_bytes(le);
+        assert_eq!(parsed.encoding, Encoding::Utf16Le);
+        assert_eq!(
+            parsed.lines,
+            vec![
+                Line {
+                    text: b"\xFF\xFEa\0".to_vec(),
+                    line_end: Some(Delim::CRLF)
+                },
+                Line {
+                    text: b"b\0".to_vec(),
+                    line_end: Some(Delim::LF)
+                },
+                Line {
+                    text: b"\x0D\x0A".to_vec(),
+                    line_end: Some(Delim::CR)
+                },
+                Line {
+                    text: Vec::new(),
+                    line_end: None
+                },
+            ]
+        );
+        let mut written = Vec::<u8>::new();
+        parsed
+            .write_to(&mut written, Delim::LF, &Options::default())
+            .unwrap();
+        assert_eq!(&written, b"\xFF\xFEa\0\n\0b\0\n\0\x0D\x0A\n\0");
+
+        let be = b"\xFE\xFF\0a\0\r\0\n\0b";
+        let parsed = FileContents::from_bytes(be);
+        assert_eq!(parsed.encoding, Encoding::Utf16Be);
+        let mut written = Vec::<u8>::new();
+        parsed
+            .write_to(&mut written, Delim::CR, &Options::default())
+            .unwrap();
+        assert_eq!(&written, b"\xFE\xFF\0a\0\r\0b");
+    }
+
+    #[test]
+    fn tiny_files() {
+        let empty = FileContents::from_bytes(b"");
+        assert_eq!(
+            empty.lines,
+            vec![Line {
+                text: Vec::new(),
+                line_end: None
+            }]
+        );
+        assert!(empty.delim_types().is_empty());
+
+        let ensure = Options {
+            final_newline: FinalNewline::Ensure,
+            ..Options::default()
+        };
+        let cases: &[(&[u8], &[u8], &[u8])] = &[
+            (b"", b"", b""),
+            (b"\r\n", b"\n", b"\r\n"),
+            (b"\n", b"\n", b"\r\n"),
+            (b"\r", b"\n", b"\r\n"),
+        ];
+        for &(raw, lf, crlf) in cases {
+            let parsed = FileContents::from_bytes(raw);
+            assert_eq!(parsed.lines.len(), if raw.is_empty() { 1 } else { 2 });
+            for &(target, expected) in &[(Delim::LF, lf), (Delim::CRLF, crlf)] {
+                for options in &[Options::default(), ensure.clone()] {
+                    let mut written = Vec::new();
+                    parsed.write_to(&mut written, target, options).unwrap();
+                    assert_eq!(written, expected, "{:?} to {}", raw, target);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_byte_files() {
+        let cr = FileContents::from_bytes(b"\r");
+        assert_eq!(
+            cr.lines,
+            vec![
+                Line {
+                    text: Vec::new(),
+                    line_end: Some(Delim::CR)
+                },
+                Line::new(),
+            ]
+        );
+        let a = FileContents::from_bytes(b"a");
+        assert_eq!(
+            a.lines,
+            vec![Line {
+                text: b"a".to_vec(),
+                line_end: None
+            }]
+        );
+        assert_eq!(a.scan().ending, Ending::Text);
+
+        // Written with each of LF, CRLF and CR.
+        let targets = [Delim::LF, Delim::CRLF, Delim::CR];
+        let cases: &[(&[u8], [&[u8]; 3])] = &[
+            (b"\r", [b"\n", b"\r\n", b"\r"]),
+            (b"\n", [b"\n", b"\r\n", b"\r"]),
+            (b"a", [b"a", b"a", b"a"]),
+            (b" ", [b" ", b" ", b" "]),
+        ];
+        for (raw, expected) in cases {
+            let parsed = FileContents::from_bytes(raw);
+            for (&target, &expected) in targets.iter().zip(expected) {
+                let mut written = Vec::new();
+                parsed
+                    .write_to(&mut written, target, &Options::default())
+                    .unwrap();
+                assert_eq!(written, expected, "{:?} to {}", raw, target);
+            }
+        }
+    }
+
+    #[test]
+    fn bom_without_delims() {
+        let strip = Options {
+            strip_bom: true,
+            ..Options::default()
+        };
+        for &(raw, text) in &[
+            (&b"\xEF\xBB\xBF"[..], &b""[..]),
+            (b"\xEF\xBB\xBFabc", b"abc"),
+        ] {
+            let parsed = FileContents::from_bytes(raw);
+            assert!(parsed.bom);
+            assert!(parsed.delim_types().is_empty());
+            let scan = parsed.scan();
+            assert!(scan.has_bom);
+            assert!(scan.delim_counts.is_empty());
+
+            let mut written = Vec::new();
+            parsed
+                .write_to(&mut written, Delim::CRLF, &Options::default())
+                .unwrap();
+            assert_eq!(written, raw);
+            let mut written = Vec::new();
+            parsed.write_to(&mut written, Delim::CRLF, &strip).unwrap();
+            assert_eq!(written, text);
+        }
+    }
+
+    #[test]
+    fn replace_tabs() {
+        let leading = Options {
+            replace_tabs: Some(4),
+            ..Options::default()
+        };
+        let all = Options {
+            all_tabs: true,
+            ..leading.clone()
+        };
+        let cases: &[(&[u8], &[u8], &[u8])] = &[
+            (b"\ta\tb\r\n", b"    a\tb\n", b"    a   b\n"),
+            (b"  \t\tx\n", b"        x\n", b"        x\n"),
+            (b"x\t\n", b"x\t\n", b"x   \n"),
+            (
+                "\u{e9}\t|\n".as_bytes(),
+                "\u{e9}\t|\n".as_bytes(),
+                "\u{e9}   |\n".as_bytes(),
+            ),
+            (b"no tabs\n", b"no tabs\n", b"no tabs\n"),
+        ];
+        for &(raw, expected_leading, expected_all) in cases {
+            let parsed = FileContents::from_bytes(raw);
+            for &(options, expected) in &[(&leading, expected_leading), (&all, expected_all)] {
+                let mut written = Vec::new();
+                parsed.write_to(&mut written, Delim::LF, options).unwrap();
+                assert_eq!(written, expected, "{:?}", String::from_utf8_lossy(raw));
+                assert_eq!(
+                    parsed.scan_with(options).tabs,
+                    raw != expected,
+                    "{:?}",
+                    String::from_utf8_lossy(raw)
+                );
+            }
+        }
+        assert!(!FileContents::from_bytes(b"\ta\n").scan().tabs);
+
+        let utf16 = Encoding::Utf16Le.encode_str("\ta\tb\n");
+        let mut written = Vec::new();
+        FileContents::from_bytes_with(&utf16, Encoding::Utf16Le)
+            .write_to(&mut written, Delim::LF, &all)
+            .unwrap();
+        assert_eq!(written, Encoding::Utf16Le.encode_str("    a   b\n"));
+    }
+
+    #[test]
+    fn round_trip_final_delim() {
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"a\n", b"a\n"),
+            (b"a\r\nb\r\n", b"a\nb\n"),
+            (b"a\rb\r\r", b"a\nb\n\n"),
+        ];
+        for &(raw, expected) in cases {
+            let parsed = FileContents::from_bytes(raw);
+            let last = parsed.lines.last().unwrap();
+            assert!(last.text.is_empty() && last.line_end.is_none());
+            let mut written = Vec::new();
+            parsed
+                .write_to(&mut written, Delim::LF, &Options::default())
+                .unwrap();
+            assert_eq!(written, expected);
+        }
+    }
+
+    #[test]
+    fn keep_regions() {
+        let raw = b"a\r\n<!-- crlf-start -->\r\nb\r\nc\r\n<!-- crlf-end -->\r\nd\r\n";
+        let options = Options {
+            regions: Some(Regions {
+                start: "crlf-start".to_string(),
+                end: "crlf-end".to_string(),
+            }),
+            ..Options::default()
+        };
+        let parsed = FileContents::from_bytes(raw);
+        let mut written = Vec::new();
+        parsed.write_to(&mut written, Delim::LF, &options).unwrap();
+        assert_eq!(
+            &written[..],
+            &b"a\n<!-- crlf-start -->\r\nb\r\nc\r\n<!-- crlf-end -->\nd\n"[..]
+        );
+
+        assert_eq!(parsed.scan_with(&options).delim_counts[&Delim::CRLF], 3);
+        let converted = FileContents::from_bytes(&written);
+        assert!(converted
+            .scan_with(&options)
+            .delim_types
+            .iter()
+            .all(|&d| d == Delim::LF));
+        assert_eq!(converted.scan().delim_types.len(), 2);
+
+        let unclosed = FileContents::from_bytes(b"a\r\ncrlf-start\r\nb\r\n");
+        let mut written = Vec::new();
+        unclosed
+            .write_to(&mut written, Delim::LF, &options)
+            .unwrap();
+        assert_eq!(&written[..], &b"a\ncrlf-start\r\nb\r\n"[..]);
+    }
+
+    #[test]
+    fn scan_ending() {
+        assert_eq!(FileContents::from_bytes(b"").scan().ending, Ending::Empty);
+        assert_eq!(
+            FileContents::from_bytes(b"\xEF\xBB\xBF").scan().ending,
+            Ending::Empty
+        );
+        assert_eq!(
+            FileContents::from_bytes(b"a\n").scan().ending,
+            Ending::Delim
+        );
+        assert_eq!(FileContents::from_bytes(b"\r").scan().ending, Ending::Delim);
+        assert_eq!(
+            FileContents::from_bytes(b"a\nb").scan().ending,
+            Ending::Text
+        );
+    }
+
+    #[test]
+    fn delim_counts() {
+        let counts = FileContents::from_bytes(b"a\nb\nc\r\nd\ne\r\n").delim_counts();
+        let expected: BTreeMap<Delim, usize> =
+            vec![(Delim::LF, 3), (Delim::CRLF, 2)].into_iter().collect();
+        assert_eq!(counts, expected);
+
+        assert!(FileContents::from_bytes(b"abc").delim_counts().is_empty());
+    }
+
+    #[test]
+    fn dominant_delim() {
+        let dominant = |raw: &[u8]| FileContents::from_bytes(raw).scan().dominant_delim();
+        assert_eq!(dominant(b"a\r\nb\nc\r\n"), Some(Delim::CRLF));
+        assert_eq!(dominant(b"a\rb\rc\n"), Some(Delim::CR));
+        assert_eq!(dominant(b"a\r\nb\n"), Some(Delim::LF));
+        assert_eq!(dominant(b"a\r\nb\r"), Some(Delim::CRLF));
+        assert_eq!(dominant(b"abc"), None);
+    }
+
+    #[test]
+    fn find_delim() {
+        let contents = FileContents::from_bytes(b"\xEF\xBB\xBFa\nbc\r\nd\n");
+        let found = contents.find_delim(|d| d != Delim::LF);
+        assert_eq!(
+            found,
+            Some(DelimPosition {
+                delim: Delim::CRLF,
+                offset: 7,
+                line: 2,
+            })
+        );
+        assert_eq!(contents.find_delim(|d| d == Delim::CR), None);
+
+        let utf16 = FileContents::from_bytes(b"\xFF\xFEa\0\r\0\n\0b\0\r\0");
+        let found = utf16.find_delim(|d| d == Delim::CR).unwrap();
+        assert_eq!((found.offset, found.line), (10, 2));
+    }
+
+    #[test]
+    fn parse_unicode_lineseps() {
+        let raw = "a\u{2028}b\u{2029}c\n".as_bytes();
+        let split = SplitOptions {
+            unicode_lineseps: true,
+            ..SplitOptions::default()
+        };
+
+        let plain = FileContents::parse(raw, Encoding::Utf8, &SplitOptions::default());
+        assert_eq!(plain, FileContents::from_bytes(raw));
+        assert_eq!(plain.lines.len(), 2);
+
+        let parsed = FileContents::parse(raw, Encoding::Utf8, &split);
+        let ends: Vec<_> = parsed.lines.iter().map(|l| l.line_end).collect();
+        assert_eq!(
+            ends,
+            vec![Some(Delim::LS), Some(Delim::PS), Some(Delim::LF), None]
+        );
+        let mut out = Vec::new();
+        parsed
+            .write_to(&mut out, Delim::LF, &Options::default())
+            .unwrap();
+        assert_eq!(out, b"a\nb\nc\n");
+
+        let utf16: Vec<u8> = "\u{FEFF}a\u{2028}b"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let parsed = FileContents::parse(&utf16, Encoding::Utf16Le, &split);
+        assert_eq!(
+            parsed.delim_types().into_iter().collect::<Vec<_>>(),
+            vec![Delim::LS]
+        );
+    }
+
+    #[test]
+    fn parse_nel() {
+        let raw = "a\u{85}b\u{2028}c\r\n".as_bytes();
+        let split = SplitOptions {
+            nel: true,
+            ..SplitOptions::default()
+        };
+        assert_eq!(FileContents::from_bytes(raw).lines.len(), 2);
+
+        let parsed = FileContents::parse(raw, Encoding::Utf8, &split);
+        let ends: Vec<_> = parsed.lines.iter().map(|l| l.line_end).collect();
+        assert_eq!(ends, vec![Some(Delim::NEL), Some(Delim::CRLF), None]);
+        let mut out = Vec::new();
+        parsed
+            .write_to(&mut out, Delim::LF, &Options::default())
+            .unwrap();
+        assert_eq!(out, "a\nb\u{2028}c\n".as_bytes());
+
+        let both = SplitOptions {
+            unicode_lineseps: true,
+            ..split
+        };
+        let parsed = FileContents::parse(raw, Encoding::Utf8, &both);
+        assert_eq!(parsed.lines.len(), 4);
+
+        let utf16: Vec<u8> = "\u{FEFF}a\u{85}b"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let parsed = FileContents::parse(&utf16, Encoding::Utf16Be, &split);
+        assert_eq!(
+            parsed.delim_types().into_iter().collect::<Vec<_>>(),
+            vec![Delim::NEL]
+        );
+    }
+
+    #[test]
+    fn trailing_cr_as_text() {
+        let split = SplitOptions {
+            trailing_cr_as_text: true,
+            ..SplitOptions::default()
+        };
+        let lines = |raw: &[u8], encoding| {
+            FileContents::parse(raw, encoding, &split)
+                .lines
+                .into_iter()
+                .map(|l| (l.text, l.line_end))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines(b"a\rb\r", Encoding::Utf8),
+            vec![(b"a".to_vec(), Some(Delim::CR)), (b"b\r".to_vec(), None)]
+        );
+        assert_eq!(
+            lines(b"a\r\n", Encoding::Utf8),
+            vec![(b"a".to_vec(), Some(Delim::CRLF)), (Vec::new(), None)]
+        );
+        assert_eq!(lines(b"\r", Encoding::Utf8), vec![(b"\r".to_vec(), None)]);
+        assert_eq!(
+            lines(b"\xFF\xFEa\0\r\0b\0\r\0", Encoding::Utf16Le),
+            vec![
+                (b"\xFF\xFEa\0".to_vec(), Some(Delim::CR)),
+                (b"b\0\r\0".to_vec(), None)
+            ]
+        );
+
+        let parsed = FileContents::parse(b"a\r\nb\r", Encoding::Utf8, &split);
+        assert_eq!(parsed.scan().delim_types.len(), 1);
+        let mut written = Vec::new();
+        parsed
+            .write_to(&mut written, Delim::LF, &Options::default())
+            .unwrap();
+        assert_eq!(written, b"a\nb\r");
+    }
+
+    #[test]
+    fn convert_forbidden_only() {
+        let options = Options {
+            forbidden: [Delim::CR, Delim::CRLF].iter().copied().collect(),
+            ..Options::default()
+        };
+        let parsed = FileContents::from_bytes(b"a\r\nb\nc\rd\n\re");
+        let mut written = Vec::new();
+        parsed.write_to(&mut written, Delim::LF, &options).unwrap();
+        assert_eq!(written, b"a\nb\nc\nd\n\ne");
+
+        let mut written = Vec::new();
+        parsed
+            .write_to(&mut written, Delim::CRLF, &options)
+            .unwrap();
+        assert_eq!(written, b"a\r\nb\nc\r\nd\n\r\ne");
+    }
+
+    #[test]
+    fn final_newline_only() {
+        let options = Options {
+            final_newline_only: true,
+            ..Options::default()
+        };
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"a\r\nb\rc", b"a\r\nb\rc\n"),
+            (b"a\r\nb\r\n", b"a\r\nb\n"),
+            (b"a\r\n\r\nb\n\r\n\n", b"a\r\n\r\nb\n"),
+            (b"\r\na\n", b"\r\na\n"),
+            (b"\n\n", b""),
+            (b"", b""),
+        ];
+        for &(raw, expected) in cases {
+            let parsed = FileContents::from_bytes(raw);
+            let mut written = Vec::new();
+            parsed.write_to(&mut written, Delim::LF, &options).unwrap();
+            assert_eq!(written, expected, "{:?}", String::from_utf8_lossy(raw));
+            assert_eq!(parsed.has_single_final_delim(Delim::LF), raw == expected);
+        }
+    }
+
+    #[test]
+    fn line_iter() {
+        let lines: Vec<_> = LineIter::new(b"abc\r\ndef\nghi\rj").collect();
+        assert_eq!(
+            lines,
+            vec![
+                (&b"abc"[..], Some(Delim::CRLF)),
+                (&b"def"[..], Some(Delim::LF)),
+                (&b"ghi"[..], Some(Delim::CR)),
+                (&b"j"[..], None),
+            ]
+        );
+
+        let lines: Vec<_> = LineIter::new(b"a\n").collect();
+        assert_eq!(lines, vec![(&b"a"[..], Some(Delim::LF)), (&b""[..], None)]);
+        let lines: Vec<_> = LineIter::new(b"").collect();
+        assert_eq!(lines, vec![(&b""[..], None)]);
+    }
+
+    #[test]
+    fn line_iter_cr_at_end() {
+        // A CR found last has no following byte, so it's always a lone CR, with each way to search.
+        for &(unicode_lineseps, nel) in
+            &[(false, false), (true, false), (false, true), (true, true)]
+        {
+            let split = SplitOptions {
+                unicode_lineseps,
+                nel,
+                ..SplitOptions::default()
+            };
+            let lines: Vec<_> = LineIter::with_options(b"a\nb\r", split).collect();
+            assert_eq!(
+                lines,
+                vec![
+                    (&b"a"[..], Some(Delim::LF)),
+                    (&b"b"[..], Some(Delim::CR)),
+                    (&b""[..], None),
+                ]
+            );
+            let lines: Vec<_> = LineIter::with_options(b"\r", split).collect();
+            assert_eq!(lines, vec![(&b""[..], Some(Delim::CR)), (&b""[..], None)]);
+        }
+    }
+
+    /// Random lines with random delimiters, and contents joining them.
+    /// The last line has no delimiter.
+    fn arb_contents() -> impl Strategy<Value = (Vec<Line>, Vec<u8>)> {
+        let delim = prop::sample::select(&Delim::ALL[..]);
+        let text = "[^\r\n\u{FEFF}]{0,8}";
+        (prop::collection::vec((text, delim), 0..16), text).prop_map(|(lines, last)| {
+            let mut lines: Vec<Line> = lines
+                .into_iter()
+                .map(|(text, d)| Line {
+                    text: text.into_bytes(),
+                    line_end: Some(d),
+                })
+                .collect();
+            lines.push(Line {
+                text: last.into_bytes(),
+                line_end: None,
+            });
+
+            let mut raw = Vec::new();
+            for l in &lines {
+                raw.extend_from_slice(&l.text);
+                if let Some(d) = l.line_end {
+                    d.write_to(&mut raw, Encoding::Utf8).unwrap();
+                }
+            }
+            (lines, raw)
+        })
+    }
+
+    /// Checks whether CR is followed by an empty line ending with LF, which is read as CRLF.
+    fn has_split_crlf(lines: &[Line]) -> bool {
+        lines.windows(2).any(|w| {
+            w[0].line_end == Some(Delim::CR)
+                && w[1].text.is_empty()
+                && w[1].line_end == Some(Delim::LF)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn parse_random_lines((lines, raw) in arb_contents()) {
+            prop_assume!(!has_split_crlf(&lines));
+            prop_assert_eq!(FileContents::from_bytes(&raw).lines, lines);
+        }
+
+        #[test]
+        fn round_trip_random_lines((_, raw) in arb_contents()) {
+            let parsed = FileContents::from_bytes(&raw);
+            for &delim in &Delim::ALL {
+                let mut written = Vec::new();
+                parsed.write_to(&mut written, delim, &Options::default()).unwrap();
+
+                let reparsed = FileContents::from_bytes(&written);
+                prop_assert!(reparsed.delim_types().iter().all(|&d| d == delim));
+                prop_assert_eq!(reparsed.lines.len(), parsed.lines.len());
+                for (after, before) in reparsed.lines.iter().zip(&parsed.lines) {
+                    prop_assert_eq!(&after.text, &before.text);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::error::{ConvertError, Result};
+
+/// Line delimiter.
+///
+/// Ordering follows the declaration, which is also the order detected types are printed in.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Delim {
+    LF,
+    CR,
+    CRLF,
+    /// U+2028 LINE SEPARATOR, recognized only with `SplitOptions::unicode_lineseps`.
+    LS,
+    /// U+2029 PARAGRAPH SEPARATOR, recognized only with `SplitOptions::unicode_lineseps`.
+    PS,
+    /// U+0085 NEXT LINE, recognized only with `SplitOptions::nel`.
+    NEL,
+}
+
+impl Delim {
+    /// All ASCII delimiter types, which are always recognized.
+    pub const ALL: [Delim; 3] = [Delim::LF, Delim::CR, Delim::CRLF];
+
+    /// Unicode delimiters recognized with `SplitOptions::unicode_lineseps` or `SplitOptions::nel`.
+    pub const UNICODE: [Delim; 3] = [Delim::LS, Delim::PS, Delim::NEL];
+
+    /// Writes the delimiter encoded in `encoding`.
+    pub fn write_to(self, w: &mut impl Write, encoding: Encoding) -> Result<()> {
+        match (self, encoding) {
+            (Delim::LF, Encoding::Utf8) => w.write_all(b"\n")?,
+            (Delim::CR, Encoding::Utf8) => w.write_all(b"\r")?,
+            (Delim::CRLF, Encoding::Utf8) => w.write_all(b"\r\n")?,
+            (Delim::LF, Encoding::Utf16Le) => w.write_all(b"\n\0")?,
+            (Delim::CR, Encoding::Utf16Le) => w.write_all(b"\r\0")?,
+            (Delim::CRLF, Encoding::Utf16Le) => w.write_all(b"\r\0\n\0")?,
+            (Delim::LF, Encoding::Utf16Be) => w.write_all(b"\0\n")?,
+            (Delim::CR, Encoding::Utf16Be) => w.write_all(b"\0\r")?,
+            (Delim::CRLF, Encoding::Utf16Be) => w.write_all(b"\0\r\0\n")?,
+            (Delim::LS, Encoding::Utf8) => w.write_all(b"\xE2\x80\xA8")?,
+            (Delim::PS, Encoding::Utf8) => w.write_all(b"\xE2\x80\xA9")?,
+            (Delim::LS, Encoding::Utf16Le) => w.write_all(b"\x28\x20")?,
+            (Delim::PS, Encoding::Utf16Le) => w.write_all(b"\x29\x20")?,
+            (Delim::LS, Encoding::Utf16Be) => w.write_all(b"\x20\x28")?,
+            (Delim::PS, Encoding::Utf16Be) => w.write_all(b"\x20\x29")?,
+            (Delim::NEL, Encoding::Utf8) => w.write_all(b"\xC2\x85")?,
+            (Delim::NEL, Encoding::Utf16Le) => w.write_all(b"\x85\0")?,
+            (Delim::NEL, Encoding::Utf16Be) => w.write_all(b"\0\x85")?,
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes of the delimiter encoded in `encoding`.
+    pub fn encoded_len(self, encoding: Encoding) -> usize {
+        match (self, encoding) {
+            (Delim::LF, Encoding::Utf8) | (Delim::CR, Encoding::Utf8) => 1,
+            (Delim::CRLF, Encoding::Utf8) | (Delim::NEL, Encoding::Utf8) => 2,
+            (Delim::LS, Encoding::Utf8) | (Delim::PS, Encoding::Utf8) => 3,
+            (Delim::CRLF, _) => 4,
+            (_, Encoding::Utf16Le) | (_, Encoding::Utf16Be) => 2,
+        }
+    }
+}
+
+impl fmt::Display for Delim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Delim::LF => write!(f, "LF"),
+            Delim::CR => write!(f, "CR"),
+            Delim::CRLF => write!(f, "CRLF"),
+            Delim::LS => write!(f, "LS"),
+            Delim::PS => write!(f, "PS"),
+            Delim::NEL => write!(f, "NEL"),
+        }
+    }
+}
+
+impl FromStr for Delim {
+    type Err = ConvertError;
+
+    /// Parses a delimiter name case-insensitively.
+    /// `unix`, `dos`, `windows`, `mac` and `classicmac` are accepted as aliases.
+    fn from_str(s: &str) -> Result<Delim> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" | "unix" => Ok(Delim::LF),
+            "crlf" | "dos" | "windows" => Ok(Delim::CRLF),
+            "cr" | "mac" | "classicmac" => Ok(Delim::CR),
+            _ => Err(ConvertError::UnknownDelim(s.to_string())),
+        }
+    }
+}
+
+/// Text encoding, which determines how delimiters are represented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 or any other ASCII compatible encoding.
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Detects UTF-16 by its BOM. Otherwise treated as UTF-8.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        match bytes {
+            [0xFF, 0xFE, ..] => Encoding::Utf16Le,
+            [0xFE, 0xFF, ..] => Encoding::Utf16Be,
+            _ => Encoding::Utf8,
+        }
+    }
+
+    /// Encodes `s` in this encoding, without BOM.
+    pub fn encode_str(self, s: &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => s.as_bytes().to_vec(),
+            Encoding::Utf16Le => s.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            Encoding::Utf16Be => s.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = ConvertError;
+
+    /// Parses `utf-8`, `utf-16le` or `utf-16be` case-insensitively, also without the hyphen.
+    fn from_str(s: &str) -> Result<Encoding> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "utf8" => Ok(Encoding::Utf8),
+            "utf16le" => Ok(Encoding::Utf16Le),
+            "utf16be" => Ok(Encoding::Utf16Be),
+            _ => Err(ConvertError::UnsupportedEncoding(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_delim() {
+        assert_eq!("lf".parse::<Delim>().unwrap(), Delim::LF);
+        assert_eq!("CRLF".parse::<Delim>().unwrap(), Delim::CRLF);
+        assert_eq!("Cr".parse::<Delim>().unwrap(), Delim::CR);
+        assert_eq!("unix".parse::<Delim>().unwrap(), Delim::LF);
+        assert_eq!("Windows".parse::<Delim>().unwrap(), Delim::CRLF);
+        assert_eq!("classicmac".parse::<Delim>().unwrap(), Delim::CR);
+
+        for d in Delim::ALL.iter() {
+            assert_eq!(d.to_string().parse::<Delim>().unwrap(), *d);
+        }
+
+        let err = "crcr".parse::<Delim>().unwrap_err();
+        assert!(err.to_string().contains("\"crcr\""));
+    }
+
+    #[test]
+    fn parse_encoding() {
+        assert_eq!("utf-8".parse::<Encoding>().unwrap(), Encoding::Utf8);
+        assert_eq!("UTF-16LE".parse::<Encoding>().unwrap(), Encoding::Utf16Le);
+        assert_eq!("utf16be".parse::<Encoding>().unwrap(), Encoding::Utf16Be);
+        match "latin1".parse::<Encoding>() {
+            Err(ConvertError::UnsupportedEncoding(s)) => assert_eq!(s, "latin1"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encoded_len() {
+        let encodings = [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be];
+        for &d in Delim::ALL.iter().chain(Delim::UNICODE.iter()) {
+            for &encoding in &encodings {
+                let mut written = Vec::new();
+                d.write_to(&mut written, encoding).unwrap();
+                assert_eq!(
+                    d.encoded_len(encoding),
+                    written.len(),
+                    "{} {:?}",
+                    d,
+                    encoding
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Per-file target delimiters read from `end_of_line` in `.editorconfig` files.
+
+use std::collections::HashMap;
+use std::env::current_dir;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+
+use fcrlf::Delim;
+
+use crate::{warn, PathFmt};
+
+const FILE_NAME: &str = ".editorconfig";
+
+/// Finds `end_of_line` for files, caching parsed `.editorconfig` files by directory.
+#[derive(Debug)]
+pub struct EditorConfig {
+    cwd: PathBuf,
+    cache: HashMap<PathBuf, Option<Rc<ConfigFile>>>,
+}
+
+impl EditorConfig {
+    pub fn new() -> Result<EditorConfig> {
+        let cwd = current_dir().context("getting current directory")?;
+        Ok(EditorConfig {
+            cwd,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Returns the target delimiter of `path`, or `None` if it's not set or `unset`.
+    ///
+    /// Files nearer to `path` take precedence, and so do later sections in a file.
+    pub fn end_of_line(&mut self, path: &Path) -> Option<Delim> {
+        let path = self.cwd.join(path);
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if let Some(file) = self.file_in(d) {
+                let relative = path.strip_prefix(d).expect("dir is an ancestor of path");
+                if let Some(end_of_line) = file.end_of_line(relative) {
+                    return end_of_line;
+                }
+                if file.root {
+                    break;
+                }
+            }
+            dir = d.parent();
+        }
+        None
+    }
+
+    fn file_in(&mut self, dir: &Path) -> Option<Rc<ConfigFile>> {
+        self.cache
+            .entry(dir.to_owned())
+            .or_insert_with(|| {
+                let path = dir.join(FILE_NAME);
+                if !path.is_file() {
+                    return None;
+                }
+                match read_to_string(&path) {
+                    Ok(text) => Some(Rc::new(ConfigFile::parse(&text, &path))),
+                    Err(e) => {
+                        warn(format_args!("reading {}: {}", PathFmt(&path), e));
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+}
+
+/// Sections of an `.editorconfig` file which have `end_of_line`.
+#[derive(Debug)]
+struct ConfigFile {
+    /// Whether `root = true` is set, which stops searching parent directories.
+    root: bool,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+struct Section {
+    glob: GlobMatcher,
+    /// `None` for `unset`.
+    end_of_line: Option<Delim>,
+}
+
+impl ConfigFile {
+    /// Parses `text` of the file at `path`, which is used in warnings.
+    /// Unknown values of `end_of_line` are ignored, as the format recommends.
+    fn parse(text: &str, path: &Path) -> ConfigFile {
+        let mut root = false;
+        // `None` in the preamble, or after a section with an invalid glob.
+        let mut glob: Option<GlobMatcher> = None;
+        let mut in_preamble = true;
+        let mut sections = Vec::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                in_preamble = false;
+                let pattern = &line[1..line.len() - 1];
+                glob = match section_glob(pattern) {
+                    Ok(glob) => Some(glob),
+                    Err(e) => {
+                        warn(format_args!("reading {}: {:#}", PathFmt(path), e));
+                        None
+                    }
+                };
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+            let value = value.to_ascii_lowercase();
+            match (key.as_str(), &glob) {
+                ("root", _) if in_preamble => root = value == "true",
+                ("end_of_line", Some(glob)) => {
+                    let end_of_line = match value.as_str() {
+                        "unset" => None,
+                        _ => match value.parse::<Delim>() {
+                            Ok(d) => Some(d),
+                            Err(_) => continue,
+                        },
+                    };
+                    sections.push(Section {
+                        glob: glob.clone(),
+                        end_of_line,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        ConfigFile { root, sections }
+    }
+
+    /// Returns `end_of_line` of the last section matching `relative` path, if any.
+    fn end_of_line(&self, relative: &Path) -> Option<Option<Delim>> {
+        self.sections
+            .iter()
+            .rev()
+            .find(|s| s.glob.is_match(relative))
+            .map(|s| s.end_of_line)
+    }
+}
+
+/// Compiles a section name into a glob matched against paths relative to the file.
+///
+/// A name without `/` matches file names in any subdirectory.
+fn section_glob(pattern: &str) -> Result<GlobMatcher> {
+    let full = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    let glob = GlobBuilder::new(&full)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("invalid section [{}]", pattern))?;
+    Ok(glob.compile_matcher())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sections() {
+        let text = "root = true\n\
+                    \n\
+                    [*]\n\
+                    end_of_line = lf\n\
+                    indent_style = space\n\
+                    \n\
+                    # Batch files\n\
+                    [*.{bat,cmd}]\n\
+                    end_of_line = CRLF\n\
+                    \n\
+                    [docs/*.txt]\n\
+                    end_of_line = unset\n\
+                    \n\
+                    [*.md]\n\
+                    end_of_line = nel\n";
+        let file = ConfigFile::parse(text, Path::new(FILE_NAME));
+        assert!(file.root);
+        assert_eq!(file.sections.len(), 3);
+
+        let eol = |p: &str| file.end_of_line(Path::new(p));
+        assert_eq!(eol("a.rs"), Some(Some(Delim::LF)));
+        assert_eq!(eol("src/a.rs"), Some(Some(Delim::LF)));
+        assert_eq!(eol("run.bat"), Some(Some(Delim::CRLF)));
+        assert_eq!(eol("tools/run.cmd"), Some(Some(Delim::CRLF)));
+        assert_eq!(eol("docs/a.txt"), Some(None));
+        assert_eq!(eol("src/docs/a.txt"), Some(Some(Delim::LF)));
+        assert_eq!(eol("README.md"), Some(Some(Delim::LF)));
+
+        let file = ConfigFile::parse("[*.txt]\nindent_size = 2\n", Path::new(FILE_NAME));
+        assert!(!file.root);
+        assert_eq!(file.end_of_line(Path::new("a.txt")), None);
+    }
+
+    #[test]
+    fn nearer_file_first() {
+        let root = std::env::temp_dir().join(format!("fcrlf-editorconfig-{}", std::process::id()));
+        let sub = root.join("win");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(root.join(FILE_NAME), "root = true\n[*]\nend_of_line = lf\n").unwrap();
+        std::fs::write(sub.join(FILE_NAME), "[*.bat]\nend_of_line = crlf\n").unwrap();
+
+        let mut editorconfig = EditorConfig::new().unwrap();
+        assert_eq!(
+            editorconfig.end_of_line(&sub.join("a.bat")),
+            Some(Delim::CRLF)
+        );
+        assert_eq!(
+            editorconfig.end_of_line(&sub.join("a.txt")),
+            Some(Delim::LF)
+        );
+        assert_eq!(
+            editorconfig.end_of_line(&root.join("a.bat")),
+            Some(Delim::LF)
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
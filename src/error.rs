@@ -0,0 +1,64 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Error of the library, so that users don't need to depend on a particular error crate.
+#[derive(Debug)]
+pub enum ConvertError {
+    /// Reading or writing contents failed.
+    Io(io::Error),
+    /// A delimiter name isn't known, with the name.
+    UnknownDelim(String),
+    /// An encoding name isn't supported, with the name.
+    UnsupportedEncoding(String),
+    /// Contents look binary, so they are not converted.
+    BinaryDetected,
+}
+
+/// Result of the library.
+pub type Result<T, E = ConvertError> = std::result::Result<T, E>;
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::Io(e) => write!(f, "{}", e),
+            ConvertError::UnknownDelim(s) => {
+                write!(f, "unknown delimiter {:?} (expected lf, crlf or cr)", s)
+            }
+            ConvertError::UnsupportedEncoding(s) => write!(
+                f,
+                "unsupported encoding {:?} (expected utf-8, utf-16le or utf-16be)",
+                s
+            ),
+            ConvertError::BinaryDetected => write!(f, "contents look binary"),
+        }
+    }
+}
+
+impl Error for ConvertError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConvertError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConvertError {
+    fn from(e: io::Error) -> ConvertError {
+        ConvertError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_source() {
+        let e = ConvertError::from(io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed"));
+        assert_eq!(e.to_string(), "pipe closed");
+        assert!(e.source().is_some());
+        assert!(ConvertError::BinaryDetected.source().is_none());
+    }
+}
@@ -0,0 +1,108 @@
+//! Files modified in a git repository, for `--git-modified`.
+
+use std::env::current_dir;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Error, Result};
+
+use crate::{path_from_bytes, relative_to, warn};
+
+/// Lists files which are staged, modified or untracked in the repository of the current directory.
+///
+/// Returns `None` with a warning if it's not in a repository or git is unavailable.
+/// Paths are relative to the current directory if possible.
+pub fn modified_files() -> Result<Option<Vec<PathBuf>>> {
+    let toplevel = match git(&["rev-parse", "--show-toplevel"]) {
+        Ok(Some(mut out)) => {
+            while out.last().is_some_and(u8::is_ascii_whitespace) {
+                out.pop();
+            }
+            path_from_bytes(&out)?
+        }
+        Ok(None) => {
+            warn(format_args!(
+                "not in a git repository, so '--git-modified' is ignored"
+            ));
+            return Ok(None);
+        }
+        Err(e) => {
+            warn(format_args!(
+                "can't run git, so '--git-modified' is ignored: {}",
+                e
+            ));
+            return Ok(None);
+        }
+    };
+
+    let out = git(&["status", "--porcelain", "-z", "--untracked-files=all"])
+        .context("running git status")?
+        .ok_or_else(|| Error::msg("git status failed"))?;
+    let cwd = current_dir().context("getting current directory")?;
+    let files = parse_porcelain(&out)?
+        .into_iter()
+        .map(|p| relative_to(&toplevel.join(p), &cwd))
+        .filter(|p| p.is_file())
+        .collect();
+    Ok(Some(files))
+}
+
+/// Runs git with `args`, returning its stdout, or `None` if it fails.
+/// Its stderr is discarded, as failures are reported by the caller.
+fn git(args: &[&str]) -> io::Result<Option<Vec<u8>>> {
+    let output = Command::new("git")
+        .args(args)
+        .stderr(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        Ok(Some(output.stdout))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parses output of `git status --porcelain -z` into paths relative to the repository root.
+///
+/// Deleted files are excluded. For renamed and copied files, only the new path is taken.
+fn parse_porcelain(out: &[u8]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let mut entries = out.split(|&b| b == 0).filter(|e| !e.is_empty());
+    while let Some(entry) = entries.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let (status, path) = (&entry[..2], &entry[3..]);
+        if status.contains(&b'R') || status.contains(&b'C') {
+            // The original path follows as a separate entry.
+            entries.next();
+        }
+        if status[1] == b'D' || (status[0] == b'D' && status[1] == b' ') {
+            continue;
+        }
+        paths.push(path_from_bytes(path)?);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn porcelain() {
+        let out = b" M src/main.rs\0M  README.md\0?? new file.txt\0 D gone.txt\0D  removed.txt\0\
+                    R  renamed.rs\0old.rs\0AM added.rs\0";
+        assert_eq!(
+            parse_porcelain(out).unwrap(),
+            vec![
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("README.md"),
+                PathBuf::from("new file.txt"),
+                PathBuf::from("renamed.rs"),
+                PathBuf::from("added.rs"),
+            ]
+        );
+        assert!(parse_porcelain(b"").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+//! Converter for file's CRLF line delimiter.
+
+use std::collections::BTreeSet;
+
+use memchr::memchr2;
+
+mod contents;
+mod delim;
+mod error;
+mod stream;
+
+pub use contents::{
+    DelimPosition, Ending, FileContents, FinalNewline, Line, LineIter, Options, Regions, Scan,
+    SplitOptions,
+};
+pub use delim::{Delim, Encoding};
+pub use error::{ConvertError, Result};
+pub use stream::{convert_stream, convert_stream_with, scan_stream, LineReader};
+
+/// Length of the head of contents checked by `is_binary`.
+pub const BINARY_CHECK_LEN: usize = 8000;
+
+/// UTF-8 encoded BOM.
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Checks whether contents look binary, by searching NUL in the first `BINARY_CHECK_LEN` bytes.
+/// For UTF-16, NUL code units are searched instead.
+pub fn is_binary(bytes: &[u8], encoding: Encoding) -> bool {
+    let len = bytes.len().min(BINARY_CHECK_LEN);
+    let bytes = &bytes[..len];
+    match encoding {
+        Encoding::Utf8 => bytes.contains(&0),
+        Encoding::Utf16Le | Encoding::Utf16Be => bytes.chunks_exact(2).any(|u| u == [0, 0]),
+    }
+}
+
+/// Collects delimiter types in UTF-8 `bytes` without splitting them into lines.
+///
+/// Scanning stops once all types in `Delim::ALL` are found.
+pub fn scan_delims(bytes: &[u8]) -> BTreeSet<Delim> {
+    let mut types = BTreeSet::new();
+    let mut rest = bytes;
+
+    while let Some(i) = memchr2(b'\r', b'\n', rest) {
+        if rest[i] == b'\n' {
+            types.insert(Delim::LF);
+            rest = &rest[i + 1..];
+        } else if rest.get(i + 1) == Some(&b'\n') {
+            types.insert(Delim::CRLF);
+            rest = &rest[i + 2..];
+        } else {
+            types.insert(Delim::CR);
+            rest = &rest[i + 1..];
+        }
+
+        if types.len() == Delim::ALL.len() {
+            break;
+        }
+    }
+
+    types
+}
+
+/// Collects delimiter types in `bytes` in a single pass, detecting UTF-16 by its BOM.
+///
+/// The result is the same as `FileContents::from_bytes(bytes).delim_types()`, but lines are not
+/// split or copied: it takes O(n) time and O(1) extra space, and stops once all types are found.
+pub fn detect_delims(bytes: &[u8]) -> BTreeSet<Delim> {
+    let to_unit: fn([u8; 2]) -> u16 = match Encoding::detect(bytes) {
+        Encoding::Utf8 => return scan_delims(bytes),
+        Encoding::Utf16Le => u16::from_le_bytes,
+        Encoding::Utf16Be => u16::from_be_bytes,
+    };
+
+    let mut types = BTreeSet::new();
+    let mut units = bytes
+        .chunks_exact(2)
+        .map(|u| to_unit([u[0], u[1]]))
+        .peekable();
+    while let Some(unit) = units.next() {
+        match unit {
+            0x000A => {
+                types.insert(Delim::LF);
+            }
+            0x000D if units.peek() == Some(&0x000A) => {
+                units.next();
+                types.insert(Delim::CRLF);
+            }
+            0x000D => {
+                types.insert(Delim::CR);
+            }
+            _ => continue,
+        }
+
+        if types.len() == Delim::ALL.len() {
+            break;
+        }
+    }
+
+    types
+}
+
+/// Converts all line delimiters in `input` to `target`.
+///
+/// UTF-16 is detected by its BOM. Other contents are left as they are.
+pub fn convert_bytes(input: &[u8], target: Delim) -> Vec<u8> {
+    let encoding = Encoding::detect(input);
+    if encoding == Encoding::Utf8 && scan_delims(input).iter().all(|&d| d == target) {
+        return input.to_vec();
+    }
+
+    normalize(input, target, &Options::default())
+}
+
+/// Converts line delimiters in `input` to `target`, applying `options`.
+///
+/// UTF-16 is detected by its BOM. Text between delimiters is preserved byte for byte,
+/// including invalid UTF-8 and NUL, unless `options` changes it, e.g. by trimming trailing whitespace.
+/// The BOM is kept unless `options.strip_bom` is set.
+pub fn normalize(input: &[u8], target: Delim, options: &Options) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    FileContents::from_bytes(input)
+        .write_to(&mut out, target, options)
+        .expect("writing to Vec never fails");
+    out
+}
+
+/// Converts like `normalize`, but fails with `ConvertError::BinaryDetected` if `input` looks binary.
+pub fn convert_text(input: &[u8], target: Delim, options: &Options) -> Result<Vec<u8>> {
+    if is_binary(input, Encoding::detect(input)) {
+        return Err(ConvertError::BinaryDetected);
+    }
+    Ok(normalize(input, target, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_binary() {
+        assert!(!is_binary(b"abc\r\ndef", Encoding::Utf8));
+        assert!(is_binary(
+            b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR",
+            Encoding::Utf8
+        ));
+        assert!(!is_binary(b"\xFF\xFEa\0\r\0\n\0", Encoding::Utf16Le));
+        assert!(is_binary(b"\xFF\xFEa\0\0\0", Encoding::Utf16Le));
+
+        let mut late_nul = vec![b'a'; BINARY_CHECK_LEN];
+        late_nul.push(0);
+        assert!(!is_binary(&late_nul, Encoding::Utf8));
+    }
+
+    #[test]
+    fn scan_delims_same_as_delim_types() {
+        let cases: &[&[u8]] = &[
+            b"",
+            b"abc",
+            b"a\nb\n",
+            b"a\r\nb\r\n",
+            b"a\rb\r",
+            b"a\r\nb\nc",
+            b"a\r\rb\n\r\n",
+            b"\r",
+            b"a\r\nb\r",
+            b"\n\r",
+        ];
+        for raw in cases {
+            assert_eq!(
+                scan_delims(raw),
+                FileContents::from_bytes(raw).delim_types(),
+                "{:?}",
+                String::from_utf8_lossy(raw)
+            );
+        }
+    }
+
+    #[test]
+    fn detect_delims_same_as_delim_types() {
+        let cases: &[&[u8]] = &[
+            b"",
+            b"\xEF\xBB\xBFa\r\nb\n",
+            b"a\r\rb\n\r\n",
+            b"\xFF\xFE",
+            b"\xFF\xFEa\0\r\0\n\0b\0\r\0",
+            b"\xFF\xFEa\0\n\0\r",
+            b"\xFE\xFF\0a\0\r\0\r\0\n\0\n",
+            // CR as the high byte of a unit isn't a delimiter.
+            b"\xFE\xFF\r\0\n\0",
+        ];
+        for raw in cases {
+            assert_eq!(
+                detect_delims(raw),
+                FileContents::from_bytes(raw).delim_types(),
+                "{:?}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn convert_bytes_to_each_delim() {
+        let input = b"a\r\nb\nc\rd";
+        assert_eq!(convert_bytes(input, Delim::LF), b"a\nb\nc\nd");
+        assert_eq!(convert_bytes(input, Delim::CRLF), b"a\r\nb\r\nc\r\nd");
+        assert_eq!(convert_bytes(input, Delim::CR), b"a\rb\rc\rd");
+        assert_eq!(convert_bytes(b"", Delim::CRLF), b"");
+    }
+
+    #[test]
+    fn normalize_keeps_text() {
+        let input = b"\xEF\xBB\xBF\xFF\x00bad \r\n\xC3(\t \rend";
+        assert_eq!(
+            normalize(input, Delim::LF, &Options::default()),
+            b"\xEF\xBB\xBF\xFF\x00bad \n\xC3(\t \nend"
+        );
+
+        let options = Options {
+            strip_bom: true,
+            final_newline: FinalNewline::Ensure,
+            trim_trailing_whitespace: true,
+            from: Some(Delim::CRLF),
+            ..Options::default()
+        };
+        assert_eq!(
+            normalize(input, Delim::LF, &options),
+            b"\xFF\x00bad\n\xC3(\rend\n"
+        );
+        assert_eq!(
+            normalize(b"\xFF\xFEa\0\r\0\n\0", Delim::CR, &Options::default()),
+            b"\xFF\xFEa\0\r\0"
+        );
+    }
+
+    #[test]
+    fn convert_text_rejects_binary() {
+        let options = Options::default();
+        assert_eq!(
+            convert_text(b"a\r\nb", Delim::LF, &options).unwrap(),
+            b"a\nb"
+        );
+        assert!(matches!(
+            convert_text(b"a\0\r\n", Delim::LF, &options),
+            Err(ConvertError::BinaryDetected)
+        ));
+    }
+}
@@ -1,327 +1,5330 @@
-use std::collections::BTreeSet;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env::{args_os, current_dir};
+use std::ffi::OsString;
 use std::fmt;
-use std::fs::{read, rename, File};
-use std::io::{BufWriter, Write};
-use std::mem::drop;
-use std::path::{Path, PathBuf};
+use std::fs::{
+    canonicalize, copy, create_dir_all, metadata, read, read_dir, remove_file, rename,
+    symlink_metadata, DirEntry, File, Metadata, OpenOptions,
+};
+use std::io::{
+    self, stdin, stdout, BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write,
+};
+use std::mem::{drop, take};
+use std::path::{Component, Path, PathBuf};
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::sync_channel;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{scope, sleep};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{Context, Error, Result};
-use clap::{App, Arg};
-use glob::glob;
+use clap::{App, AppSettings, Arg, ArgMatches, Shell};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use glob::{glob_with, MatchOptions, Pattern};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::{Deserialize, Serialize};
+
+use editorconfig::EditorConfig;
+use fcrlf::{
+    convert_stream, convert_stream_with, is_binary, scan_delims, scan_stream, Delim, DelimPosition,
+    Encoding, Ending, FileContents, FinalNewline, Line, Options, Regions, Scan, SplitOptions,
+    BINARY_CHECK_LEN, UTF8_BOM,
+};
+use prompt::{Aborted, Answer, Prompt};
+use report::{write_json_line, write_report, ReportEntry};
+use temp::TempPath;
+
+mod archive;
+mod editorconfig;
+mod git;
+mod prompt;
+mod report;
+mod temp;
 
 const BUF_SIZE: usize = 1 << 20;
 
-fn main() -> Result<()> {
-    let matches = App::new("fcrlf")
-        .about("Converter of file's CRLF line delimiter.")
-        .arg(
-            Arg::with_name("crlf")
-                .short("w")
-                .long("crlf")
-                .conflicts_with_all(&["lf", "cr"])
-                .help("Convert to CRLF"),
-        )
-        .arg(
-            Arg::with_name("lf")
-                .short("u")
-                .long("lf")
-                .conflicts_with_all(&["crlf", "cr"])
-                .help("Convert to LF"),
-        )
-        .arg(
-            Arg::with_name("cr")
-                .short("m")
-                .long("cr")
-                .conflicts_with_all(&["lf", "crlf"])
-                .help("Convert to CR"),
-        )
-        .arg(
-            Arg::with_name("detect")
-                .short("d")
-                .long("detect")
-                .help("Detect only and don't perform conversion"),
-        )
-        .arg(
-            Arg::with_name("patterns")
-                .required(true)
-                .multiple(true)
-                .help("Files to convert"),
-        )
-        .get_matches();
+const STDIN_PATTERN: &str = "-";
+
+const EXIT_NEEDS_CONVERSION: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+
+/// Set by `--quiet`. It's global because warnings are printed from everywhere.
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// Directory given by `--relative-to`, and the current directory, both absolute.
+static DISPLAY_BASE: OnceLock<(PathBuf, PathBuf)> = OnceLock::new();
+
+const ENCODINGS: [&str; 4] = ["auto", "utf-8", "utf-16le", "utf-16be"];
+
+/// Options giving a target, which take precedence over `.editorconfig` and `target` in the config.
+const TARGET_ARGS: [&str; 8] = [
+    "to",
+    "to-bytes",
+    "auto-to",
+    "check-consistency",
+    "match-first",
+    "crlf",
+    "lf",
+    "cr",
+];
+
+fn main() {
+    temp::remove_on_interrupt();
+    match run() {
+        Ok(code) => exit(code),
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// Prints a warning to stderr, unless suppressed by `--quiet`.
+fn warn(message: fmt::Arguments) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("Warning: {}", message);
+    }
+}
+
+/// Defines command line arguments.
+fn app() -> App<'static, 'static> {
+    App::new("fcrlf")
+        .about("Converter of file's CRLF line delimiter.")
+        .arg(
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .value_name("DELIM")
+                .conflicts_with_all(&["crlf", "lf", "cr"])
+                .validator(|s| s.parse::<Delim>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Target delimiter: lf, crlf or cr"),
+        )
+        .arg(
+            Arg::with_name("to-bytes")
+                .long("to-bytes")
+                .takes_value(true)
+                .value_name("HEX")
+                .conflicts_with_all(&["to", "crlf", "lf", "cr"])
+                .validator(|s| parse_hex_bytes(&s).map(|_| ()).map_err(|e| e.to_string()))
+                .help("Target delimiter as hex bytes, like '0A 0D', written as it is"),
+        )
+        .arg(
+            Arg::with_name("auto-to")
+                .long("auto-to")
+                .conflicts_with_all(&["to", "to-bytes", "crlf", "lf", "cr", "from"])
+                .help("Convert each file to its most common delimiter, so only mixed ones are flagged"),
+        )
+        .arg(
+            Arg::with_name("check-consistency")
+                .long("check-consistency")
+                .conflicts_with_all(&["to", "to-bytes", "auto-to", "match-first", "accept", "forbid", "crlf", "lf", "cr", "from"])
+                .help("Flag files using more than one delimiter, whichever they are, or convert them to their most common one"),
+        )
+        .arg(
+            Arg::with_name("match-first")
+                .long("match-first")
+                .conflicts_with_all(&["to", "to-bytes", "auto-to", "accept", "crlf", "lf", "cr"])
+                .help("Convert all files to the most common delimiter of the first file"),
+        )
+        .arg(Arg::with_name("editorconfig").long("editorconfig").help(
+            "Use end_of_line in .editorconfig as the target of each file, unless a target is given",
+        ))
+        .arg(
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .value_name("DELIM")
+                .validator(|s| s.parse::<Delim>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Convert only this delimiter, leaving others as they are"),
+        )
+        .arg(
+            Arg::with_name("forbid")
+                .long("forbid")
+                .takes_value(true)
+                .use_delimiter(true)
+                .value_name("DELIMS")
+                .conflicts_with_all(&["from", "auto-to"])
+                .validator(|s| s.parse::<Delim>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Detect or convert only these delimiters separated by commas, like cr,crlf; no target is needed to detect"),
+        )
+        .arg(
+            Arg::with_name("accept")
+                .long("accept")
+                .takes_value(true)
+                .use_delimiter(true)
+                .value_name("DELIMS")
+                .conflicts_with_all(&["from", "forbid", "auto-to", "to-bytes"])
+                .validator(|s| s.parse::<Delim>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Leave files using only one of these delimiters separated by commas, like lf,crlf, and convert others to the target"),
+        )
+        .arg(
+            Arg::with_name("reject-mixed")
+                .long("reject-mixed")
+                .help("Convert files using several delimiters entirely, even if '--from', '--forbid' or '--accept' keeps all of them"),
+        )
+        .arg(
+            Arg::with_name("crlf")
+                .short("w")
+                .long("crlf")
+                .hidden(true)
+                .conflicts_with_all(&["lf", "cr"])
+                .help("Convert to CRLF"),
+        )
+        .arg(
+            Arg::with_name("lf")
+                .short("u")
+                .long("lf")
+                .hidden(true)
+                .conflicts_with_all(&["crlf", "cr"])
+                .help("Convert to LF"),
+        )
+        .arg(
+            Arg::with_name("cr")
+                .short("m")
+                .long("cr")
+                .hidden(true)
+                .conflicts_with_all(&["lf", "crlf"])
+                .help("Convert to CR"),
+        )
+        .arg(
+            Arg::with_name("detect")
+                .short("d")
+                .long("detect")
+                .help("Detect only and don't perform conversion"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .short("c")
+                .long("check")
+                .help("Detect only, and exit with 1 if any file needs conversion"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .short("n")
+                .long("dry-run")
+                .help("Show files which would be converted and don't perform conversion"),
+        )
+        .arg(
+            Arg::with_name("show-diff")
+                .long("show-diff")
+                .help("Show changes of files which would be converted as diff, without writing"),
+        )
+        .arg(
+            Arg::with_name("diff-exit")
+                .long("diff-exit")
+                .help("Show diffs like '--show-diff', and exit with 1 if any file needs conversion"),
+        )
+        .arg(
+            Arg::with_name("count-only")
+                .long("count-only")
+                .conflicts_with_all(&[
+                    "dry-run",
+                    "show-diff",
+                    "diff-exit",
+                    "format",
+                    "print0",
+                    "interactive",
+                    "verbose",
+                    "progress",
+                ])
+                .help("Print only the number of files which need conversion, and exit with 1 if any"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["text", "json", "jsonl"])
+                .default_value("text")
+                .help("Output format of detected files"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .value_name("WHEN")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .help("Colorize detected files, 'auto' colorizes only on a terminal"),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .help("Convert files in matched directories recursively"),
+        )
+        .arg(
+            Arg::with_name("preserve-mtime")
+                .long("preserve-mtime")
+                .help("Keep modified time of converted files"),
+        )
+        .arg(
+            Arg::with_name("inplace-rewrite")
+                .long("inplace-rewrite")
+                .help("Rewrite files with several hard links in place instead of replacing them, so all links see the change"),
+        )
+        .arg(
+            Arg::with_name("check-unchanged")
+                .long("check-unchanged")
+                .help("Leave files changed by others while converting them, instead of replacing them"),
+        )
+        .arg(
+            Arg::with_name("backup")
+                .short("b")
+                .long("backup")
+                .help("Keep original files as backup"),
+        )
+        .arg(
+            Arg::with_name("backup-suffix")
+                .long("backup-suffix")
+                .takes_value(true)
+                .value_name("SUFFIX")
+                .default_value(".bak")
+                .help("Suffix of backup files"),
+        )
+        .arg(
+            Arg::with_name("overwrite-backup")
+                .long("overwrite-backup")
+                .requires("backup")
+                .help("Overwrite existing backup files instead of failing"),
+        )
+        .arg(
+            Arg::with_name("transaction")
+                .long("transaction")
+                .help("Replace files only after all files are converted successfully"),
+        )
+        .arg(
+            Arg::with_name("interactive")
+                .short("i")
+                .long("interactive")
+                .conflicts_with_all(&[
+                    "detect",
+                    "check",
+                    "dry-run",
+                    "show-diff",
+                    "diff-exit",
+                    "quiet",
+                    "progress",
+                ])
+                .help("Ask y(es), n(o), a(ll) or q(uit) on the terminal before converting each file"),
+        )
+        .arg(
+            Arg::with_name("max-file-size")
+                .long("max-file-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .validator(|s| parse_size(&s).map(|_| ()).map_err(|e| e.to_string()))
+                .help("Skip files larger than SIZE, like 500K, 50M or 1G"),
+        )
+        .arg(
+            Arg::with_name("regions")
+                .long("regions")
+                .help("Keep delimiters between lines containing crlf-start and crlf-end"),
+        )
+        .arg(
+            Arg::with_name("skip-if-contains")
+                .long("skip-if-contains")
+                .takes_value(true)
+                .value_name("MARKER")
+                .validator(|s| {
+                    if s.is_empty() {
+                        Err("MARKER must not be empty".to_string())
+                    } else {
+                        Ok(())
+                    }
+                })
+                .help("Skip files containing MARKER in their first 8000 bytes, like generated files"),
+        )
+        .arg(
+            Arg::with_name("verify")
+                .long("verify")
+                .conflicts_with_all(&["to-bytes", "zip"])
+                .help("Read converted files again and check that they have only target delimiters"),
+        )
+        .arg(
+            Arg::with_name("fail-fast")
+                .long("fail-fast")
+                .help("Stop at the first file that fails, instead of continuing with others"),
+        )
+        .arg(
+            Arg::with_name("temp-dir")
+                .long("temp-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Create temporary files in DIR instead of next to converted files"),
+        )
+        .arg(
+            Arg::with_name("out-dir")
+                .long("out-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .conflicts_with_all(&["backup", "inplace-rewrite"])
+                .help("Write converted files under DIR at the same relative paths, leaving originals"),
+        )
+        .arg(
+            Arg::with_name("temp-suffix")
+                .long("temp-suffix")
+                .takes_value(true)
+                .value_name("SUFFIX")
+                .default_value(".tmp")
+                .validator(|s| {
+                    if s.is_empty() || s.contains(&['/', '\\'][..]) {
+                        Err("suffix should be non-empty and contain no path separators".to_string())
+                    } else {
+                        Ok(())
+                    }
+                })
+                .help("Suffix of temporary files, followed by a number"),
+        )
+        .arg(
+            Arg::with_name("clean")
+                .long("clean")
+                .conflicts_with_all(&["detect", "check", "dry-run", "transaction"])
+                .help("Remove temporary files like NAME.tmp0 left next to matched files, instead of converting"),
+        )
+        .arg(
+            Arg::with_name("gzip")
+                .long("gzip")
+                .help("Treat all files as gzip-compressed, not only ones ending in .gz"),
+        )
+        .arg(
+            Arg::with_name("zip")
+                .long("zip")
+                .help("Convert text entries in files ending in .zip, rewriting the archives"),
+        )
+        .arg(
+            Arg::with_name("zip-entries")
+                .long("zip-entries")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .requires("zip")
+                .help("Convert only entries of zip archives matching GLOB, which can be repeated"),
+        )
+        .arg(
+            Arg::with_name("encoding")
+                .long("encoding")
+                .takes_value(true)
+                .possible_values(&ENCODINGS)
+                .default_value("auto")
+                .help("Encoding of files, 'auto' detects UTF-16 by BOM"),
+        )
+        .arg(
+            Arg::with_name("strip-bom")
+                .long("strip-bom")
+                .help("Remove UTF-8 BOM"),
+        )
+        .arg(
+            Arg::with_name("ensure-final-newline")
+                .long("ensure-final-newline")
+                .conflicts_with("no-final-newline")
+                .help("Add a delimiter at the end of files if missing"),
+        )
+        .arg(
+            Arg::with_name("no-final-newline")
+                .long("no-final-newline")
+                .conflicts_with("ensure-final-newline")
+                .help("Remove a delimiter at the end of files"),
+        )
+        .arg(
+            Arg::with_name("require-final-newline")
+                .long("require-final-newline")
+                .conflicts_with("no-final-newline")
+                .help("Report files without a delimiter at the end, leaving them as they are"),
+        )
+        .arg(
+            Arg::with_name("final-newline-only")
+                .long("final-newline-only")
+                .conflicts_with_all(&["from", "forbid", "ensure-final-newline", "no-final-newline", "regions", "first-offset"])
+                .help("Convert only the delimiter at the end of files, ensuring exactly one there"),
+        )
+        .arg(
+            Arg::with_name("trim-trailing-whitespace")
+                .long("trim-trailing-whitespace")
+                .help("Remove spaces and tabs at the end of lines"),
+        )
+        .arg(
+            Arg::with_name("replace-tabs")
+                .long("replace-tabs")
+                .takes_value(true)
+                .value_name("N")
+                .help("Expand tabs in indentation to spaces, with tab stops every N columns"),
+        )
+        .arg(
+            Arg::with_name("all-tabs")
+                .long("all-tabs")
+                .requires("replace-tabs")
+                .help("Expand all tabs with '--replace-tabs', not only ones in indentation"),
+        )
+        .arg(
+            Arg::with_name("unicode-lineseps")
+                .long("unicode-lineseps")
+                .help("Treat U+2028 and U+2029 as delimiters and convert them too"),
+        )
+        .arg(
+            Arg::with_name("nel")
+                .long("nel")
+                .help("Treat NEL (U+0085) as a delimiter and convert it too"),
+        )
+        .arg(
+            Arg::with_name("treat-trailing-cr-as-text")
+                .long("treat-trailing-cr-as-text")
+                .help("Leave a lone CR at the end of files as text, not as a delimiter"),
+        )
+        .arg(
+            Arg::with_name("binary")
+                .long("binary")
+                .aliases(&["text", "force"])
+                .help("Convert files even if they look binary"),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .takes_value(true)
+                .value_name("N")
+                .help("Number of files processed in parallel, which may exceed the number of CPUs [default: number of CPUs]"),
+        )
+        .arg(
+            Arg::with_name("io-bound-jobs")
+                .long("io-bound-jobs")
+                .help("Read files ahead in 8 threads per CPU while jobs convert them, for high-latency storage like NFS"),
+        )
+        .arg(
+            Arg::with_name("follow-symlinks")
+                .long("follow-symlinks")
+                .help("Convert targets of symlinks to files, instead of skipping them"),
+        )
+        .arg(
+            Arg::with_name("dereference-and-warn")
+                .long("dereference-and-warn")
+                .help("Warn about matched symlinks whose targets don't exist, instead of skipping them silently"),
+        )
+        .arg(
+            Arg::with_name("no-ignore")
+                .long("no-ignore")
+                .help("Don't skip files ignored by .gitignore or .ignore"),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .long("hidden")
+                .help("Include hidden files and directories in wildcards and recursion"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .help("Log each file action to stderr, and temporary files too if repeated"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .conflicts_with_all(&["verbose", "progress"])
+                .help("Print nothing but errors"),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .long("progress")
+                .help("Show progress of processed files on stderr"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .help("Print numbers of files containing each delimiter"),
+        )
+        .arg(
+            Arg::with_name("summary-delimiters")
+                .long("summary-delimiters")
+                .help("Print numbers of files with each single delimiter, mixed ones and ones without delimiters"),
+        )
+        .arg(
+            Arg::with_name("histogram")
+                .long("histogram")
+                .alias("delimiter-histogram")
+                .help("Print total counts of each delimiter across all files as a table"),
+        )
+        .arg(
+            Arg::with_name("report")
+                .long("report")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("Write the result of every file to PATH, as CSV if it ends with .csv or JSON otherwise"),
+        )
+        .arg(
+            Arg::with_name("warn-mixed")
+                .long("warn-mixed")
+                .help("Warn about files containing multiple types of delimiters"),
+        )
+        .arg(
+            Arg::with_name("counts")
+                .long("counts")
+                .help("Print number of each delimiter in detected files"),
+        )
+        .arg(
+            Arg::with_name("mmap")
+                .long("mmap")
+                .help("Memory-map files to scan them in detect modes, instead of reading them"),
+        )
+        .arg(
+            Arg::with_name("max-offenders")
+                .long("max-offenders")
+                .takes_value(true)
+                .value_name("N")
+                .conflicts_with_all(&["format", "show-diff", "diff-exit"])
+                .help("Report only files with at most N delimiters to convert"),
+        )
+        .arg(
+            Arg::with_name("first-offset")
+                .long("first-offset")
+                .help("Print line and byte offset of the first non-target delimiter in detected files"),
+        )
+        .arg(
+            Arg::with_name("check-writable")
+                .long("check-writable")
+                .help("Report detected files which can't be replaced, e.g. because of permissions"),
+        )
+        .arg(
+            Arg::with_name("git-modified")
+                .long("git-modified")
+                .help("Process only files staged, modified or untracked in git, or all of them without patterns"),
+        )
+        .arg(
+            Arg::with_name("include")
+                .long("include")
+                .takes_value(true)
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Process only files matching GLOB, which can be repeated"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .takes_value(true)
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Skip files matching GLOB, which can be repeated"),
+        )
+        .arg(
+            Arg::with_name("files-from")
+                .long("files-from")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Read paths to convert from FILE, or '-' for stdin, one per line"),
+        )
+        .arg(
+            Arg::with_name("null")
+                .long("null")
+                .requires("files-from")
+                .help("Paths in --files-from are separated by NUL instead of newline"),
+        )
+        .arg(
+            Arg::with_name("print0")
+                .long("print0")
+                .conflicts_with_all(&["show-diff", "diff-exit", "format"])
+                .help("Print only paths of detected files, each followed by NUL, as for 'xargs -0'"),
+        )
+        .arg(
+            Arg::with_name("stdin-filename")
+                .long("stdin-filename")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Name of stdin shown in messages and JSON output"),
+        )
+        .arg(
+            Arg::with_name("line-buffered")
+                .long("line-buffered")
+                .help("Flush converted stdin to stdout after each line, for interactive pipelines"),
+        )
+        .arg(
+            Arg::with_name("relative-to")
+                .long("relative-to")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Show paths in messages and output relative to DIR"),
+        )
+        .arg(
+            Arg::with_name("error-on-no-match")
+                .long("error-on-no-match")
+                .help("Exit with an error if a pattern matches no files"),
+        )
+        .arg(
+            Arg::with_name("no-config")
+                .long("no-config")
+                .help("Don't read defaults from .fcrlf.toml"),
+        )
+        .arg(
+            Arg::with_name("generate-completions")
+                .long("generate-completions")
+                .takes_value(true)
+                .value_name("SHELL")
+                .possible_values(&Shell::variants())
+                .help("Print a completion script for SHELL to stdout"),
+        )
+        .arg(
+            Arg::with_name("patterns")
+                .required_unless_one(&["files-from", "git-modified", "generate-completions"])
+                .multiple(true)
+                .help("Files to convert, or '-' for stdin"),
+        )
+}
+
+fn run() -> Result<i32> {
+    let args: Vec<OsString> = args_os().collect();
+    let matches = app().get_matches_from_safe(&args);
+    let matches = match matches {
+        Ok(m) => m,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e.message);
+            exit(EXIT_ERROR);
+        }
+        Err(e) => e.exit(),
+    };
+
+    if let Some(shell) = matches.value_of("generate-completions") {
+        let shell = shell.parse::<Shell>().map_err(Error::msg)?;
+        app().gen_completions_to("fcrlf", shell, &mut stdout());
+        return Ok(0);
+    }
+
+    QUIET.store(
+        matches.is_present("quiet") || matches.is_present("count-only"),
+        Ordering::Relaxed,
+    );
+    if let Some(dir) = matches.value_of("relative-to") {
+        let base = canonicalize(dir)
+            .with_context(|| format!("resolving directory of '--relative-to': {:?}", dir))?;
+        let cwd = current_dir().context("getting current directory")?;
+        let _ = DISPLAY_BASE.set((base, cwd));
+    }
+
+    let project = if matches.is_present("no-config") {
+        ProjectConfig::default()
+    } else {
+        let cwd = current_dir().context("getting current directory")?;
+        match ProjectConfig::find(&cwd)? {
+            Some((path, project)) => {
+                if matches.occurrences_of("verbose") > 0 {
+                    eprintln!("using config {}", PathFmt(&path));
+                }
+                project
+                    .check_with(&args, &matches)
+                    .with_context(|| format!("invalid config {}", PathFmt(&path)))?;
+                project
+            }
+            None => ProjectConfig::default(),
+        }
+    };
+    // Flags and options given on the command line take precedence over the config.
+    let flag = |name: &str| matches.is_present(name) || project.flag(name);
+    let value_of = |name: &str| {
+        if matches.occurrences_of(name) > 0 {
+            matches.value_of(name)
+        } else {
+            project.value(name).or_else(|| matches.value_of(name))
+        }
+    };
+
+    let diff_exit = matches.is_present("diff-exit");
+    let count_only = matches.is_present("count-only");
+    let check = matches.is_present("check") || diff_exit || count_only;
+    let dry_run = matches.is_present("dry-run");
+    let show_diff = matches.is_present("show-diff") || diff_exit;
+    let do_covert = !matches.is_present("detect") && !check && !dry_run && !show_diff;
+    // Consistency within each file is what `--auto-to` leaves, so it's checked in the same way.
+    let auto_to = matches.is_present("auto-to") || matches.is_present("check-consistency");
+    let match_first = matches.is_present("match-first");
+    let delim_bytes = matches
+        .value_of("to-bytes")
+        .map(parse_hex_bytes)
+        .transpose()?;
+    let explicit_target = TARGET_ARGS.iter().any(|name| matches.is_present(name));
+    // Targets given on the command line take precedence over `.editorconfig`.
+    let mut editorconfig = if flag("editorconfig") && !explicit_target {
+        Some(EditorConfig::new()?)
+    } else {
+        None
+    };
+    let forbidden = matches
+        .values_of("forbid")
+        .unwrap_or_default()
+        .map(|s| s.parse::<Delim>())
+        .collect::<Result<BTreeSet<Delim>, _>>()?;
+    // Files are only checked for forbidden delimiters, so there is nothing to convert them to.
+    let forbid_only = !forbidden.is_empty()
+        && !do_covert
+        && !dry_run
+        && !explicit_target
+        && value_of("to").is_none();
+    let default_target = match () {
+        // Only placeholders, because given bytes or dominant delimiters are written instead.
+        () if delim_bytes.is_some() || auto_to || match_first || forbid_only => Some(Delim::LF),
+        () if matches.is_present("crlf") => Some(Delim::CRLF),
+        () if matches.is_present("lf") => Some(Delim::LF),
+        () if matches.is_present("cr") => Some(Delim::CR),
+        _ => match value_of("to") {
+            Some(s) => Some(s.parse::<Delim>()?),
+            None if editorconfig.is_some() || matches.is_present("clean") => None,
+            None => {
+                return Err(Error::msg(
+                    "No target delimiter is specified, use '--to lf', '--to crlf', '--to cr' \
+                     or '--editorconfig', or set 'target' in .fcrlf.toml.",
+                ))
+            }
+        },
+    };
+    // Only a placeholder, because files without `end_of_line` are skipped.
+    let target_delim = default_target.unwrap_or(Delim::LF);
+    let from = value_of("from").map(|s| s.parse::<Delim>()).transpose()?;
+    if !forbid_only && delim_bytes.is_none() && !match_first && forbidden.contains(&target_delim) {
+        return Err(Error::msg(format!(
+            "The target {} is forbidden by '--forbid'.",
+            target_delim
+        )));
+    }
+    let mut accepted = matches
+        .values_of("accept")
+        .unwrap_or_default()
+        .map(|s| s.parse::<Delim>())
+        .collect::<Result<BTreeSet<Delim>, _>>()?;
+    if !accepted.is_empty() {
+        accepted.insert(target_delim);
+    }
+    let mut target_delim_set = kept_delims(from, &forbidden);
+    // Any delimiter is converted to given bytes, which are not detected as a delimiter.
+    if delim_bytes.is_none() && !forbid_only {
+        target_delim_set.insert(target_delim);
+    }
+
+    let config = Config {
+        do_covert,
+        dry_run,
+        show_diff,
+        target_delim,
+        target_delim_set,
+        auto_to,
+        accepted,
+        reject_mixed: flag("reject-mixed"),
+        preserve_mtime: flag("preserve-mtime"),
+        inplace_rewrite: flag("inplace-rewrite"),
+        check_unchanged: flag("check-unchanged"),
+        max_file_size: matches
+            .value_of("max-file-size")
+            .map(parse_size)
+            .transpose()?,
+        skip_marker: value_of("skip-if-contains").map(str::to_string),
+        verify: matches.is_present("verify"),
+        process_binary: flag("binary"),
+        format: match matches.value_of("format") {
+            Some("json") => Format::Json,
+            Some("jsonl") => Format::Jsonl,
+            _ => Format::Text,
+        },
+        temp_dir: matches.value_of("temp-dir").map(PathBuf::from),
+        out_dir: matches.value_of("out-dir").map(PathBuf::from),
+        temp_suffix: value_of("temp-suffix").unwrap_or(".tmp").to_string(),
+        transaction: if matches.is_present("transaction") {
+            Some(Arc::new(Mutex::new(Vec::new())))
+        } else {
+            None
+        },
+        interactive: if matches.is_present("interactive") {
+            Some(Arc::new(Prompt::default()))
+        } else {
+            None
+        },
+        histogram: if matches.is_present("histogram") {
+            Some(Arc::new(Mutex::new(BTreeMap::new())))
+        } else {
+            None
+        },
+        gzip: matches.is_present("gzip"),
+        zip: if matches.is_present("zip") {
+            let entries = matches.values_of("zip-entries").unwrap_or_default();
+            Some(Arc::new(PathFilter::new(entries, None)?))
+        } else {
+            None
+        },
+        backup: if flag("backup") {
+            Some(Backup {
+                suffix: value_of("backup-suffix")
+                    .expect("backup-suffix has default value")
+                    .to_string(),
+                overwrite: matches.is_present("overwrite-backup"),
+            })
+        } else {
+            None
+        },
+        encoding: value_of("encoding")
+            .filter(|&s| s != "auto")
+            .map(str::parse::<Encoding>)
+            .transpose()?,
+        options: Options {
+            strip_bom: flag("strip-bom"),
+            final_newline: match () {
+                () if matches.is_present("ensure-final-newline") => FinalNewline::Ensure,
+                () if matches.is_present("no-final-newline") => FinalNewline::Remove,
+                () if project.ensure_final_newline => FinalNewline::Ensure,
+                () if project.no_final_newline => FinalNewline::Remove,
+                _ => FinalNewline::Keep,
+            },
+            trim_trailing_whitespace: flag("trim-trailing-whitespace"),
+            replace_tabs: value_of("replace-tabs")
+                .map(|s| match s.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(Error::msg(format!("invalid tab width: {:?}", s))),
+                })
+                .transpose()?,
+            all_tabs: flag("all-tabs"),
+            from,
+            forbidden,
+            delim_bytes,
+            regions: if flag("regions") {
+                Some(Regions {
+                    start: "crlf-start".to_string(),
+                    end: "crlf-end".to_string(),
+                })
+            } else {
+                None
+            },
+            final_newline_only: flag("final-newline-only"),
+        },
+        stdin_name: matches
+            .value_of("stdin-filename")
+            .unwrap_or(STDIN_PATTERN)
+            .to_string(),
+        line_buffered: flag("line-buffered"),
+        color: match matches.value_of("color") {
+            Some("always") => ColorChoice::Always,
+            Some("never") => ColorChoice::Never,
+            _ => ColorChoice::Auto,
+        },
+        split: SplitOptions {
+            unicode_lineseps: flag("unicode-lineseps"),
+            nel: flag("nel"),
+            trailing_cr_as_text: flag("treat-trailing-cr-as-text"),
+        },
+        require_final_newline: flag("require-final-newline"),
+        counts: matches.is_present("counts"),
+        max_offenders: matches
+            .value_of("max-offenders")
+            .map(|s| {
+                s.parse::<usize>()
+                    .with_context(|| format!("invalid number of '--max-offenders': {:?}", s))
+            })
+            .transpose()?,
+        first_offset: matches.is_present("first-offset"),
+        check_writable: matches.is_present("check-writable"),
+        print0: flag("print0"),
+        mmap: flag("mmap"),
+        warn_mixed: flag("warn-mixed"),
+        verbose: matches.occurrences_of("verbose"),
+        // Only the count is printed, as with `--quiet` otherwise.
+        quiet: matches.is_present("quiet") || count_only,
+        full_scan: matches.is_present("stats")
+            || matches.is_present("counts")
+            || matches.is_present("max-offenders")
+            || matches.is_present("report")
+            || matches.is_present("summary-delimiters")
+            || matches.is_present("histogram")
+            || matches.occurrences_of("verbose") > 0
+            || matches.is_present("interactive")
+            || auto_to,
+    };
+    let recursive = flag("recursive");
+    // Options only for reporting files, which would be ignored when converting them.
+    let detect_only = [
+        ("--format json", config.format == Format::Json),
+        ("--first-offset", config.first_offset),
+        ("--mmap", config.mmap),
+        ("--max-offenders", config.max_offenders.is_some()),
+        ("--check-writable", config.check_writable),
+        ("--print0", config.print0),
+    ];
+    if config.do_covert {
+        if let Some((name, _)) = detect_only.iter().find(|&&(_, given)| given) {
+            return Err(Error::msg(format!(
+                "'{}' is available only with '--detect', '--check' or '--dry-run'.",
+                name
+            )));
+        }
+    }
+    let format_name = matches.value_of("format").unwrap_or_default();
+    if config.format != Format::Text && config.quiet {
+        return Err(Error::msg(format!(
+            "'--format {}' can't be used with '--quiet'.",
+            format_name
+        )));
+    }
+    if config.format != Format::Text && config.show_diff {
+        return Err(Error::msg(format!(
+            "'--format {}' can't be used with '--show-diff'.",
+            format_name
+        )));
+    }
+
+    let patterns = matches.values_of("patterns").unwrap_or_default();
+    let files_from = matches.value_of("files-from");
+    if config.interactive.is_some() {
+        if !stdin().is_terminal() {
+            return Err(Error::msg(
+                "'--interactive' needs stdin to be a terminal, to read answers from it.",
+            ));
+        }
+        if files_from == Some(STDIN_PATTERN) || patterns.clone().any(|pat| pat == STDIN_PATTERN) {
+            return Err(Error::msg(
+                "'--interactive' can't read files from stdin, because answers are read from it.",
+            ));
+        }
+    }
+    if files_from == Some(STDIN_PATTERN) && patterns.clone().any(|pat| pat == STDIN_PATTERN) {
+        return Err(Error::msg(
+            "'-' can't be used as pattern with '--files-from -', because stdin is the file list.",
+        ));
+    }
+
+    let mut ignore_filter = if flag("no-ignore") {
+        None
+    } else {
+        Some(IgnoreFilter::new()?)
+    };
+    let hidden = flag("hidden");
+    let warn_broken = flag("dereference-and-warn");
+
+    let jobs = job_count(&matches, &project)?;
+    let readers = io_bound_jobs(&matches, &project);
+    // Files are asked one at a time anyway, and in order with a single thread.
+    let (jobs, readers) = if config.interactive.is_some() {
+        (1, None)
+    } else {
+        (jobs, readers)
+    };
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .context("creating thread pool")?;
+
+    let mut inputs = Vec::new();
+
+    for pat in patterns {
+        if pat == STDIN_PATTERN {
+            inputs.push(Input::Stdin);
+            continue;
+        }
+
+        let found_before = inputs.len();
+        for p in glob_paths(pat, ignore_filter.as_mut(), hidden)? {
+            if recursive && p.is_dir() {
+                let mut files = Vec::new();
+                walk_dir(&p, &mut files, ignore_filter.as_mut(), hidden, warn_broken);
+                inputs.extend(files.into_iter().map(Input::File));
+                continue;
+            }
+            if !p.exists() || !p.is_file() {
+                if warn_broken && is_broken_symlink(&p) {
+                    warn(format_args!("skipping broken symlink: {}", PathFmt(&p)));
+                }
+                continue;
+            }
+
+            inputs.push(Input::File(p));
+        }
+
+        if inputs.len() == found_before {
+            if matches.is_present("error-on-no-match") {
+                return Err(Error::msg(format!("pattern matched no files: {:?}", pat)));
+            }
+            warn(format_args!("pattern matched no files: {:?}", pat));
+        }
+    }
+
+    if let Some(source) = files_from {
+        let list = read_file_list(source, matches.is_present("null"))?;
+        // Listed paths are explicitly named, so they are processed even if ignored.
+        for p in list {
+            if recursive && p.is_dir() {
+                let mut files = Vec::new();
+                walk_dir(&p, &mut files, ignore_filter.as_mut(), hidden, warn_broken);
+                inputs.extend(files.into_iter().map(Input::File));
+                continue;
+            }
+            if p.is_dir() {
+                continue;
+            }
+
+            inputs.push(Input::File(p));
+        }
+    }
+
+    if matches.is_present("git-modified") {
+        if let Some(modified) = git::modified_files()? {
+            if matches.values_of("patterns").is_none() && files_from.is_none() {
+                inputs = modified.into_iter().map(Input::File).collect();
+            } else {
+                let modified: HashSet<PathBuf> = modified
+                    .iter()
+                    .filter_map(|p| canonicalize(p).ok())
+                    .collect();
+                inputs.retain(|input| match input {
+                    Input::Stdin => true,
+                    Input::File(p) => canonicalize(p).is_ok_and(|p| modified.contains(&p)),
+                });
+            }
+        }
+    }
+
+    let path_filter = PathFilter::new(
+        matches
+            .values_of("include")
+            .unwrap_or_default()
+            .chain(project.include.iter().map(String::as_str)),
+        matches
+            .values_of("exclude")
+            .unwrap_or_default()
+            .chain(project.exclude.iter().map(String::as_str)),
+    )?;
+    inputs.retain(|input| match input {
+        Input::Stdin => true,
+        Input::File(p) => path_filter.is_match(p),
+    });
+
+    if flag("follow-symlinks") {
+        inputs = resolve_symlinks(inputs);
+    }
+
+    if matches.is_present("clean") {
+        return Ok(clean_tmp_files(&inputs, &config));
+    }
+
+    // The first file decides the target of all files, so it's scanned before processing in parallel.
+    let config = match inputs.first() {
+        Some(Input::File(p)) if match_first => config.with_target(first_file_target(p, &config)?),
+        Some(Input::Stdin) if match_first => {
+            return Err(Error::msg(
+                "'--match-first' can't take the target from stdin, which can't be read twice.",
+            ))
+        }
+        _ => config,
+    };
+
+    if config.format == Format::Jsonl
+        && config.do_covert
+        && inputs.iter().any(|input| matches!(input, Input::Stdin))
+    {
+        return Err(Error::msg(
+            "'--format jsonl' can't be used when converting stdin, which is written to stdout.",
+        ));
+    }
+
+    // Targets from `.editorconfig` are found before processing in parallel, because they're cached.
+    let targets: Vec<Option<Delim>> = match &mut editorconfig {
+        Some(editorconfig) => inputs
+            .iter()
+            .map(|input| match input {
+                Input::Stdin if matches.is_present("stdin-filename") => {
+                    editorconfig.end_of_line(Path::new(&config.stdin_name))
+                }
+                Input::Stdin => None,
+                Input::File(p) => editorconfig.end_of_line(p),
+            })
+            .collect(),
+        None => vec![None; inputs.len()],
+    };
+
+    let progress = if matches.is_present("progress") {
+        Some(Progress::new(inputs.len()))
+    } else {
+        None
+    };
+    // A transaction fails as a whole, so there is no point in continuing.
+    let fail_fast = matches.is_present("fail-fast") || config.transaction.is_some();
+    let process = |i: usize, read_ahead: Option<ReadAhead>| {
+        let (input, target) = (&inputs[i], &targets[i]);
+        let convert = |config: &Config| match (input, read_ahead) {
+            (Input::Stdin, _) => convert_stdin(config),
+            (Input::File(p), Some(read_ahead)) => convert_read_ahead(p, config, read_ahead),
+            (Input::File(p), None) => convert_file(p, config),
+        };
+        let outcome = match (target, default_target) {
+            (Some(target), _) => convert(&config.with_target(*target)),
+            (None, Some(_)) => convert(&config),
+            (None, None) => Ok(Outcome::NoTarget),
+        };
+        if let Some(progress) = &progress {
+            progress.inc();
+        }
+        let outcome = match outcome {
+            Err(e) if !fail_fast && !e.is::<Aborted>() => {
+                eprintln!("Error: {:?}", e);
+                Ok(Outcome::Failed(format!("{:#}", e)))
+            }
+            outcome => outcome,
+        };
+        // Each file is written as soon as it's processed.
+        if let (Format::Jsonl, Ok(outcome)) = (config.format, &outcome) {
+            let target = config.entry_target(outcome, *target);
+            let entry =
+                ReportEntry::new(input, outcome, target, config.do_covert, &config.stdin_name);
+            write_json_line(&entry)?;
+        }
+        outcome
+    };
+    let outcomes = pool.install(|| match readers {
+        Some(readers) => process_read_ahead(&inputs, &config, readers, process),
+        None => (0..inputs.len())
+            .into_par_iter()
+            .map(|i| process(i, None))
+            .collect::<Result<Vec<Outcome>>>(),
+    });
+    if let Some(progress) = &progress {
+        progress.finish();
+    }
+    if let Some(transaction) = &config.transaction {
+        let staged = take(
+            &mut *transaction
+                .lock()
+                .expect("transaction lock is not poisoned"),
+        );
+        finish_transaction(staged, &outcomes)?;
+    }
+    let outcomes = outcomes?;
+    let flagged = outcomes.iter().any(Outcome::is_flagged);
+    if count_only {
+        let count = outcomes.iter().filter(|o| o.is_flagged()).count();
+        println!("{}", count);
+    }
+
+    let has_files = inputs.iter().any(|input| matches!(input, Input::File(_)));
+    if !config.quiet && has_files {
+        let summary = Summary::new(&outcomes);
+        eprintln!("{}", summary.message(config.do_covert));
+        if matches.is_present("stats") {
+            eprintln!("{}", summary.stats());
+        }
+        if matches.is_present("summary-delimiters") {
+            eprintln!("{}", summary.delimiter_tally());
+        }
+    }
+    if let Some(histogram) = &config.histogram {
+        if !config.quiet {
+            let counts = histogram.lock().expect("histogram lock is not poisoned");
+            eprint!("{}", histogram_table(&counts));
+        }
+    }
+
+    if config.format == Format::Json {
+        let entries: Vec<JsonEntry> = inputs
+            .iter()
+            .zip(outcomes.iter())
+            .filter_map(|(input, outcome)| JsonEntry::new(input, outcome, &config.stdin_name))
+            .collect();
+        let stdout = stdout();
+        let mut w = stdout.lock();
+        serde_json::to_writer_pretty(&mut w, &entries).context("writing JSON to stdout")?;
+        writeln!(w).context("writing JSON to stdout")?;
+    }
+
+    if let Some(path) = matches.value_of_os("report") {
+        let entries: Vec<ReportEntry> = inputs
+            .iter()
+            .zip(outcomes.iter())
+            .zip(targets.iter())
+            .map(|((input, outcome), target)| {
+                let target = config.entry_target(outcome, *target);
+                ReportEntry::new(input, outcome, target, config.do_covert, &config.stdin_name)
+            })
+            .collect();
+        write_report(Path::new(path), &entries)?;
+    }
+
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome, Outcome::Failed(_)))
+        .count();
+    if failed > 0 {
+        eprintln!("Error: {} failed", FilesFmt(failed));
+        Ok(EXIT_ERROR)
+    } else if check && flagged {
+        Ok(EXIT_NEEDS_CONVERSION)
+    } else {
+        Ok(0)
+    }
+}
+
+#[derive(Debug)]
+enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+#[derive(Debug)]
+enum Outcome {
+    /// Contains only target delimiters, so left as it is.
+    Correct(BTreeSet<Delim>),
+    /// Contains non-target delimiters, so converted or reported.
+    /// The second set is of delimiters converted, without ones kept by `--from` or others.
+    NeedsConversion(BTreeSet<Delim>, BTreeSet<Delim>),
+    /// Contains only target delimiters but no final newline, so reported and left as it is.
+    MissingFinalNewline(BTreeSet<Delim>),
+    /// Skipped because it looks binary.
+    Binary,
+    /// Skipped because it's a symlink, which would be replaced with a regular file.
+    Symlink,
+    /// Skipped because it's larger than `--max-file-size`.
+    TooLarge,
+    /// Skipped because it contains the `--skip-if-contains` marker.
+    Marked,
+    /// Left because converting it was declined by `--interactive`.
+    Declined,
+    /// Skipped because `.editorconfig` has no `end_of_line` for it, and no target is given.
+    NoTarget,
+    /// Failed with an error, which is already printed. It's kept for `--report`.
+    Failed(String),
+}
+
+impl Outcome {
+    /// Checks whether the file is reported in detect modes, and fails `--check`.
+    fn is_flagged(&self) -> bool {
+        matches!(
+            self,
+            Outcome::NeedsConversion(..) | Outcome::MissingFinalNewline(_)
+        )
+    }
+}
+
+/// Counts of outcomes over a run.
+#[derive(Debug, Default)]
+struct Summary {
+    needs_conversion: usize,
+    correct: usize,
+    /// Files only flagged by `--require-final-newline`.
+    missing_final_newline: usize,
+    binary: usize,
+    symlink: usize,
+    too_large: usize,
+    no_target: usize,
+    marked: usize,
+    declined: usize,
+    /// Number of files containing each delimiter type.
+    delim_files: BTreeMap<Delim, usize>,
+    /// Number of files containing only each delimiter type.
+    single_delim: BTreeMap<Delim, usize>,
+    /// Number of files containing multiple delimiter types.
+    mixed: usize,
+    /// Number of files containing no delimiters.
+    no_delim: usize,
+}
+
+impl Summary {
+    fn new(outcomes: &[Outcome]) -> Summary {
+        let mut summary = Summary::default();
+        for outcome in outcomes {
+            let delim_types = match outcome {
+                Outcome::Correct(types) => {
+                    summary.correct += 1;
+                    types
+                }
+                Outcome::NeedsConversion(types, _) => {
+                    summary.needs_conversion += 1;
+                    types
+                }
+                Outcome::MissingFinalNewline(types) => {
+                    summary.missing_final_newline += 1;
+                    types
+                }
+                Outcome::Binary => {
+                    summary.binary += 1;
+                    continue;
+                }
+                Outcome::Symlink => {
+                    summary.symlink += 1;
+                    continue;
+                }
+                Outcome::TooLarge => {
+                    summary.too_large += 1;
+                    continue;
+                }
+                Outcome::NoTarget => {
+                    summary.no_target += 1;
+                    continue;
+                }
+                Outcome::Marked => {
+                    summary.marked += 1;
+                    continue;
+                }
+                Outcome::Declined => {
+                    summary.declined += 1;
+                    continue;
+                }
+                Outcome::Failed(_) => continue,
+            };
+            for &d in delim_types {
+                *summary.delim_files.entry(d).or_insert(0) += 1;
+            }
+            match delim_types.iter().next() {
+                None => summary.no_delim += 1,
+                Some(&d) if delim_types.len() == 1 => {
+                    *summary.single_delim.entry(d).or_insert(0) += 1
+                }
+                Some(_) => summary.mixed += 1,
+            }
+        }
+        summary
+    }
+
+    fn message(&self, converted: bool) -> String {
+        let needs_conversion = if converted {
+            format!("converted {}", FilesFmt(self.needs_conversion))
+        } else if self.needs_conversion == 1 {
+            format!("{} needs conversion", FilesFmt(self.needs_conversion))
+        } else {
+            format!("{} need conversion", FilesFmt(self.needs_conversion))
+        };
+        let missing_final_newline = if self.missing_final_newline > 0 {
+            format!(", {} without final newline", self.missing_final_newline)
+        } else {
+            String::new()
+        };
+        let symlink = if self.symlink > 0 {
+            format!(", {} skipped (symlink)", self.symlink)
+        } else {
+            String::new()
+        };
+        let too_large = if self.too_large > 0 {
+            format!(", {} skipped (too large)", self.too_large)
+        } else {
+            String::new()
+        };
+        let no_target = if self.no_target > 0 {
+            format!(", {} skipped (no end_of_line)", self.no_target)
+        } else {
+            String::new()
+        };
+        let marked = if self.marked > 0 {
+            format!(", {} skipped (marker)", self.marked)
+        } else {
+            String::new()
+        };
+        let declined = if self.declined > 0 {
+            format!(", {} skipped (declined)", self.declined)
+        } else {
+            String::new()
+        };
+        format!(
+            "{}, {} already correct{}, {} skipped (binary){}{}{}{}{}",
+            needs_conversion,
+            self.correct,
+            missing_final_newline,
+            self.binary,
+            symlink,
+            too_large,
+            no_target,
+            marked,
+            declined
+        )
+    }
+
+    /// Unicode separators are included only when found.
+    fn stats(&self) -> String {
+        let unicode = Delim::UNICODE
+            .iter()
+            .filter(|d| self.delim_files.contains_key(d));
+        Delim::ALL
+            .iter()
+            .chain(unicode)
+            .map(|d| {
+                let n = self.delim_files.get(d).copied().unwrap_or(0);
+                format!("{}: {}", d, FilesFmt(n))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Formats numbers of files by their delimiter types, like `LF only: 3 files, ..., mixed: 1 file`.
+    fn delimiter_tally(&self) -> String {
+        let unicode = Delim::UNICODE
+            .iter()
+            .filter(|d| self.single_delim.contains_key(d));
+        let mut tally: Vec<String> = Delim::ALL
+            .iter()
+            .chain(unicode)
+            .map(|d| {
+                let n = self.single_delim.get(d).copied().unwrap_or(0);
+                format!("{} only: {}", d, FilesFmt(n))
+            })
+            .collect();
+        tally.push(format!("mixed: {}", FilesFmt(self.mixed)));
+        tally.push(format!("no delimiter: {}", FilesFmt(self.no_delim)));
+        tally.join(", ")
+    }
+}
+
+/// Formats total counts of delimiters as a table with aligned columns, one delimiter per line.
+/// Unicode separators are included only if found.
+fn histogram_table(counts: &BTreeMap<Delim, usize>) -> String {
+    let rows: Vec<(String, String)> = Delim::ALL
+        .iter()
+        .chain(Delim::UNICODE.iter().filter(|d| counts.contains_key(d)))
+        .map(|d| {
+            (
+                d.to_string(),
+                counts.get(d).copied().unwrap_or(0).to_string(),
+            )
+        })
+        .collect();
+    let name_width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let count_width = rows.iter().map(|(_, n)| n.len()).max().unwrap_or(0);
+    rows.iter()
+        .map(|(name, n)| format!("{:<name_width$}  {:>count_width$}\n", name, n))
+        .collect()
+}
+
+/// Progress of processed files, shown on stderr.
+///
+/// On a terminal, a bar is redrawn in place and cleared at the end.
+/// Otherwise, a line is printed periodically.
+#[derive(Debug)]
+struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    is_tty: bool,
+    last_shown: Mutex<Instant>,
+}
+
+impl Progress {
+    const TTY_INTERVAL: Duration = Duration::from_millis(100);
+    const LINE_INTERVAL: Duration = Duration::from_secs(1);
+    const BAR_WIDTH: usize = 30;
+
+    fn new(total: usize) -> Progress {
+        Progress {
+            total,
+            done: AtomicUsize::new(0),
+            is_tty: io::stderr().is_terminal(),
+            last_shown: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Counts a processed file, and shows progress if the interval has passed.
+    fn inc(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let interval = if self.is_tty {
+            Self::TTY_INTERVAL
+        } else {
+            Self::LINE_INTERVAL
+        };
+        let mut last_shown = self
+            .last_shown
+            .lock()
+            .expect("progress lock is not poisoned");
+        if last_shown.elapsed() < interval && done < self.total {
+            return;
+        }
+        *last_shown = Instant::now();
+
+        if self.is_tty {
+            eprint!("\r{}", self.bar(done));
+        } else {
+            eprintln!("progress: {}/{} files", done, self.total);
+        }
+    }
+
+    /// Clears the bar on a terminal.
+    fn finish(&self) {
+        let _lock = self
+            .last_shown
+            .lock()
+            .expect("progress lock is not poisoned");
+        if self.is_tty {
+            eprint!("\r\x1b[K");
+        }
+    }
+
+    fn bar(&self, done: usize) -> String {
+        let filled = match self.total {
+            0 => Self::BAR_WIDTH,
+            total => done * Self::BAR_WIDTH / total,
+        };
+        format!(
+            "[{}{}] {}/{} files",
+            "#".repeat(filled),
+            " ".repeat(Self::BAR_WIDTH - filled),
+            done,
+            self.total
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    /// A line of JSON for each file, written as soon as it's processed.
+    Jsonl,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonEntry<'a> {
+    path: String,
+    delimiters: &'a BTreeSet<Delim>,
+    needs_conversion: bool,
+    /// Present only for files flagged by `--require-final-newline`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    missing_final_newline: bool,
+}
+
+impl<'a> JsonEntry<'a> {
+    fn new(input: &Input, outcome: &'a Outcome, stdin_name: &str) -> Option<JsonEntry<'a>> {
+        let path = match input {
+            Input::Stdin => stdin_name.to_string(),
+            Input::File(p) => display_path(p).to_string_lossy().to_string(),
+        };
+        let missing_final_newline = matches!(outcome, Outcome::MissingFinalNewline(_));
+        let (delimiters, needs_conversion) = match outcome {
+            Outcome::Correct(types) => (types, false),
+            Outcome::NeedsConversion(types, _) => (types, true),
+            Outcome::MissingFinalNewline(types) => (types, false),
+            Outcome::Binary
+            | Outcome::Symlink
+            | Outcome::TooLarge
+            | Outcome::NoTarget
+            | Outcome::Marked
+            | Outcome::Declined
+            | Outcome::Failed(_) => return None,
+        };
+        Some(JsonEntry {
+            path,
+            delimiters,
+            needs_conversion,
+            missing_final_newline,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    do_covert: bool,
+    dry_run: bool,
+    show_diff: bool,
+    target_delim: Delim,
+    target_delim_set: BTreeSet<Delim>,
+    /// Decide the target for each file by `Scan::dominant_delim`.
+    auto_to: bool,
+    /// Files using only one of these delimiters are left as they are, by `--accept`.
+    /// It includes the target if not empty.
+    accepted: BTreeSet<Delim>,
+    /// Convert files using several delimiters even if all of them are kept, by `--reject-mixed`.
+    reject_mixed: bool,
+    preserve_mtime: bool,
+    /// Rewrite files with several hard links in place, instead of renaming over them.
+    inplace_rewrite: bool,
+    /// Check that files are unchanged since they were read, just before replacing them.
+    check_unchanged: bool,
+    /// Skip files larger than this in bytes.
+    max_file_size: Option<u64>,
+    /// Skip files containing this in their first `BINARY_CHECK_LEN` bytes.
+    skip_marker: Option<String>,
+    /// Check delimiters of converted files by reading them again.
+    verify: bool,
+    process_binary: bool,
+    format: Format,
+    backup: Option<Backup>,
+    /// `None` to create temporary files in the same directories as converted files.
+    temp_dir: Option<PathBuf>,
+    /// Directory where converted files are written instead of replacing originals.
+    out_dir: Option<PathBuf>,
+    /// Temporary files are named like `name.tmpN` with this in place of `.tmp`.
+    temp_suffix: String,
+    /// Files staged by `--transaction`, which replace the originals after all files are converted.
+    transaction: Option<Arc<Mutex<Vec<StagedFile>>>>,
+    /// Asks before converting each file, by `--interactive`.
+    interactive: Option<Arc<Prompt>>,
+    /// Total counts of each delimiter in all files, summed for `--histogram`.
+    histogram: Option<Arc<Mutex<BTreeMap<Delim, usize>>>>,
+    /// Treat all files as gzip-compressed.
+    gzip: bool,
+    /// Convert entries of zip archives matching this filter, if given by `--zip`.
+    zip: Option<Arc<PathFilter>>,
+    /// `None` to detect by BOM.
+    encoding: Option<Encoding>,
+    options: Options,
+    split: SplitOptions,
+    /// Name of stdin in messages.
+    stdin_name: String,
+    /// Flush stdout after each line of streamed stdin.
+    line_buffered: bool,
+    color: ColorChoice,
+    /// Flag files without a final newline, which are not converted for it.
+    require_final_newline: bool,
+    /// Report number of each delimiter instead of their types.
+    counts: bool,
+    /// Report only files with at most this number of delimiters to convert.
+    max_offenders: Option<usize>,
+    /// Report position of the first non-target delimiter.
+    first_offset: bool,
+    /// Check that files needing conversion can be replaced, without converting them.
+    check_writable: bool,
+    /// Print detected paths followed by NUL, without annotations.
+    print0: bool,
+    /// Scan memory-mapped files instead of reading them.
+    mmap: bool,
+    warn_mixed: bool,
+    /// Log file actions at 1, and temporary files at 2 or more.
+    verbose: u64,
+    /// Print nothing but errors.
+    quiet: bool,
+    /// Scan whole files even if it's unnecessary to decide conversion.
+    full_scan: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Backup {
+    suffix: String,
+    overwrite: bool,
+}
+
+impl Backup {
+    fn path(&self, path: &Path) -> PathBuf {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(&self.suffix);
+        path.with_file_name(file_name)
+    }
+}
+
+impl Config {
+    fn needs_conversion(&self, scan: &Scan) -> bool {
+        let trailing_whitespace = self.options.trim_trailing_whitespace && scan.trailing_whitespace;
+        let tabs = self.options.replace_tabs.is_some() && scan.tabs;
+        let mixed = self.reject_mixed && scan.delim_types.len() > 1;
+        let final_newline = match self.options.final_newline {
+            FinalNewline::Keep => false,
+            FinalNewline::Ensure => scan.ending == Ending::Text,
+            FinalNewline::Remove => scan.ending == Ending::Delim,
+        };
+
+        let target = self.target_for(scan);
+        scan.delim_types.iter().any(|&d| !self.is_target(d, target))
+            || (self.options.strip_bom && scan.has_bom)
+            || final_newline
+            || trailing_whitespace
+            || tabs
+            || mixed
+    }
+
+    /// Checks whether loaded contents need conversion.
+    /// With `--final-newline-only`, it's decided by their end instead of the delimiters they contain.
+    fn contents_need_conversion(&self, contents: &FileContents, scan: &Scan) -> bool {
+        if !self.options.final_newline_only {
+            return self.needs_conversion(scan);
+        }
+        !contents.has_single_final_delim(self.target_for(scan))
+            || (self.options.strip_bom && scan.has_bom)
+            || (self.options.trim_trailing_whitespace && scan.trailing_whitespace)
+            || (self.options.replace_tabs.is_some() && scan.tabs)
+    }
+
+    /// Checks whether the head of contents contains the `--skip-if-contains` marker.
+    /// For UTF-16, the marker is searched in the same encoding.
+    fn has_marker(&self, contents: &[u8], encoding: Encoding) -> bool {
+        let marker = match &self.skip_marker {
+            Some(marker) => marker,
+            None => return false,
+        };
+        let marker = encoding.encode_str(marker);
+        let head = &contents[..contents.len().min(BINARY_CHECK_LEN)];
+        head.windows(marker.len()).any(|w| w == marker)
+    }
+
+    /// Checks whether only delimiters decide conversion, not other content options.
+    fn only_delims(&self) -> bool {
+        !self.options.strip_bom
+            && self.options.final_newline == FinalNewline::Keep
+            && !self.options.trim_trailing_whitespace
+            && self.options.replace_tabs.is_none()
+            && !self.split.unicode_lineseps
+            && !self.split.nel
+            && !self.split.trailing_cr_as_text
+            && !self.require_final_newline
+            && self.options.regions.is_none()
+            && !self.options.final_newline_only
+            && self.options.delim_bytes.is_none()
+    }
+
+    /// Returns the config for a file whose target is given by `.editorconfig`.
+    fn with_target(&self, target: Delim) -> Config {
+        let mut target_delim_set = kept_delims(self.options.from, &self.options.forbidden);
+        target_delim_set.insert(target);
+        Config {
+            target_delim: target,
+            target_delim_set,
+            ..self.clone()
+        }
+    }
+
+    /// Checks whether `d` is left as it is when converting to `target`.
+    fn is_target(&self, d: Delim, target: Delim) -> bool {
+        if self.target_per_file() {
+            d == target
+        } else {
+            self.target_delim_set.contains(&d)
+        }
+    }
+
+    /// Checks whether stdin can be converted line by line, without reading it all first.
+    fn streams_stdin(&self, encoding: Encoding) -> bool {
+        self.do_covert
+            && encoding == Encoding::Utf8
+            && !self.split.unicode_lineseps
+            && !self.split.nel
+            && !self.split.trailing_cr_as_text
+            && !self.first_offset
+            && !self.options.final_newline_only
+            && !self.target_per_file()
+            && !self.reject_mixed
+            && self.options.delim_bytes.is_none()
+    }
+
+    /// Checks whether the target is decided for each file, by `--auto-to` or `--accept`.
+    fn target_per_file(&self) -> bool {
+        self.auto_to || !self.accepted.is_empty()
+    }
+
+    /// Returns options to convert contents, which convert all delimiters of mixed ones with
+    /// `--reject-mixed`, even if `--from` or `--forbid` keeps them.
+    fn options_for(&self, scan: &Scan) -> Cow<'_, Options> {
+        if self.reject_mixed && scan.delim_types.len() > 1 {
+            Cow::Owned(Options {
+                from: None,
+                forbidden: BTreeSet::new(),
+                ..self.options.clone()
+            })
+        } else {
+            Cow::Borrowed(&self.options)
+        }
+    }
+
+    /// Returns the target delimiter for contents, which is decided for each file with `--auto-to`.
+    /// With `--accept`, contents using only one accepted delimiter keep it.
+    fn target_for(&self, scan: &Scan) -> Delim {
+        if self.auto_to {
+            return scan.dominant_delim().unwrap_or(Delim::LF);
+        }
+        let mut types = scan.delim_types.iter();
+        match (types.next(), types.next()) {
+            (Some(&d), None) if self.accepted.contains(&d) => d,
+            _ => self.target_delim,
+        }
+    }
+
+    /// Returns the check of files converted to `target`, if `--verify` is given.
+    fn verifier(&self, target: Delim, encoding: Encoding, gzip: bool) -> Option<Verify> {
+        if !self.verify {
+            return None;
+        }
+        let allowed = if self.target_per_file() {
+            [target].iter().copied().collect()
+        } else {
+            self.target_delim_set.clone()
+        };
+        Some(Verify {
+            allowed,
+            final_delim: Some(target).filter(|_| self.options.final_newline_only),
+            encoding,
+            gzip,
+            split: self.split,
+            regions: self.options.regions.clone(),
+        })
+    }
+
+    /// Returns the target delimiter shown in messages.
+    fn target_name(&self, scan: &Scan) -> String {
+        match &self.options.delim_bytes {
+            Some(bytes) => HexFmt(bytes).to_string(),
+            None => self.target_for(scan).to_string(),
+        }
+    }
+
+    /// Returns the target shown in reports, which is `auto` with `--auto-to`.
+    fn report_target(&self) -> String {
+        match &self.options.delim_bytes {
+            Some(bytes) => HexFmt(bytes).to_string(),
+            None if self.auto_to => "auto".to_string(),
+            None => self.target_delim.to_string(),
+        }
+    }
+
+    /// Asks whether to convert a file with `--interactive`, returning `false` to leave it.
+    /// Answering quit fails with `Aborted`.
+    fn confirm(&self, name: &dyn fmt::Display, scan: &Scan) -> Result<bool> {
+        let prompt = match &self.interactive {
+            Some(prompt) => prompt,
+            None => return Ok(true),
+        };
+        let summary = format!(
+            "{} ({} to {})",
+            name,
+            DelimCountsFmt(&scan.delim_counts),
+            self.target_name(scan)
+        );
+        match prompt
+            .confirm(&summary)
+            .context("reading answer from stdin")?
+        {
+            Answer::Yes | Answer::All => Ok(true),
+            Answer::No => Ok(false),
+            Answer::Quit => Err(Error::new(Aborted)),
+        }
+    }
+
+    /// Returns the target in an entry of `--report` or `--format jsonl`, where `target` is from
+    /// `.editorconfig` if any.
+    fn entry_target(&self, outcome: &Outcome, target: Option<Delim>) -> Option<String> {
+        match (outcome, target) {
+            (Outcome::NoTarget, _) => None,
+            (_, Some(target)) => Some(target.to_string()),
+            (_, None) => Some(self.report_target()),
+        }
+    }
+
+    /// Prints `message` to stderr if verbosity is at least `level`.
+    fn log(&self, level: u64, message: impl FnOnce() -> String) {
+        if self.verbose >= level {
+            eprintln!("{}", message());
+        }
+    }
+
+    fn warn_if_mixed(&self, name: &dyn fmt::Display, delim_types: &BTreeSet<Delim>) {
+        if self.warn_mixed && delim_types.len() > 1 {
+            warn(format_args!(
+                "mixed delimiters in {}: {}",
+                name,
+                DelimSetFmt(delim_types)
+            ));
+        }
+    }
+
+    /// Formats a report line for a file which needs conversion.
+    /// `is_tty` tells whether the line is printed to a terminal, to decide coloring.
+    fn detected_line(
+        &self,
+        name: &dyn fmt::Display,
+        scan: &Scan,
+        first: Option<DelimPosition>,
+        needs_conversion: bool,
+        is_tty: bool,
+    ) -> String {
+        let color = self.color.enabled(is_tty);
+        let delims = if self.counts {
+            DelimCountsFmt(&scan.delim_counts).to_string()
+        } else {
+            DelimSetFmt(&scan.delim_types).to_string()
+        };
+        let name = Paint::new(color, ANSI_RED, name);
+        let delims = Paint::new(color, ANSI_YELLOW, delims);
+        let mut line = if self.dry_run && needs_conversion {
+            let target = Paint::new(color, ANSI_GREEN, self.target_name(scan));
+            format!("{}: {} -> {}", name, delims, target)
+        } else {
+            format!("{}: {}", name, delims)
+        };
+        let forbidden: BTreeSet<Delim> = scan
+            .delim_types
+            .intersection(&self.options.forbidden)
+            .copied()
+            .collect();
+        if !forbidden.is_empty() {
+            line.push_str(&format!(" (forbidden {})", DelimSetFmt(&forbidden)));
+        }
+        if let Some(first) = first {
+            line.push_str(&format!(
+                " (first {} at line {}, byte {})",
+                first.delim, first.line, first.offset
+            ));
+        }
+        if self.lacks_final_newline(scan) {
+            line.push_str(" (no final newline)");
+        }
+        line
+    }
+
+    /// Formats the record of a detected file, which is its report line followed by newline,
+    /// or only its name followed by NUL with `--print0`.
+    fn detected_record(
+        &self,
+        name: &dyn fmt::Display,
+        scan: &Scan,
+        first: Option<DelimPosition>,
+        needs_conversion: bool,
+        is_tty: bool,
+    ) -> String {
+        if self.print0 {
+            format!("{}\0", name)
+        } else {
+            let line = self.detected_line(name, scan, first, needs_conversion, is_tty);
+            format!("{}\n", line)
+        }
+    }
+
+    /// Counts delimiters which are converted in `scan`, which need a full scan.
+    fn offenders(&self, scan: &Scan) -> usize {
+        let target = self.target_for(scan);
+        scan.delim_counts
+            .iter()
+            .filter(|&(&d, _)| !self.is_target(d, target))
+            .map(|(_, &n)| n)
+            .sum()
+    }
+
+    /// Finds the first non-target delimiter, if `--first-offset` is given.
+    fn first_offending(&self, contents: &FileContents, target: Delim) -> Option<DelimPosition> {
+        if !self.first_offset {
+            return None;
+        }
+        contents.find_delim(|d| !self.is_target(d, target))
+    }
+
+    /// Formats changes by conversion of `contents` with `options` as a diff, with visible delimiters.
+    fn diff(
+        &self,
+        name: &dyn fmt::Display,
+        contents: &FileContents,
+        target: Delim,
+        options: &Options,
+    ) -> Result<String> {
+        let mut converted = Vec::new();
+        contents.write_to(&mut converted, target, options)?;
+        let converted = FileContents::parse(&converted, contents.encoding, &self.split);
+        Ok(diff_lines(name, contents, &converted))
+    }
+
+    /// Checks whether `scan` is flagged by `--require-final-newline`.
+    /// Files to which the final newline is added by conversion are not flagged.
+    fn lacks_final_newline(&self, scan: &Scan) -> bool {
+        self.require_final_newline
+            && self.options.final_newline != FinalNewline::Ensure
+            && scan.ending == Ending::Text
+    }
+
+    /// Reports an input if it's flagged, and makes its outcome.
+    ///
+    /// Detected inputs are reported to stdout, or stderr if `to_stderr`.
+    /// In convert mode, only missing final newlines are warned.
+    fn outcome(
+        &self,
+        name: &dyn fmt::Display,
+        scan: Scan,
+        first: Option<DelimPosition>,
+        needs_conversion: bool,
+        to_stderr: bool,
+    ) -> Outcome {
+        let missing_final_newline = self.lacks_final_newline(&scan);
+        if let Some(histogram) = &self.histogram {
+            let mut histogram = histogram.lock().expect("histogram lock is not poisoned");
+            for (&d, &n) in &scan.delim_counts {
+                *histogram.entry(d).or_insert(0) += n;
+            }
+        }
+
+        let target = self.target_for(&scan);
+        let converted_from: BTreeSet<Delim> = scan
+            .delim_types
+            .iter()
+            .copied()
+            .filter(|&d| !self.is_target(d, target))
+            .collect();
+        if needs_conversion && self.do_covert {
+            // Only other options may change files without delimiters to convert.
+            let from = if converted_from.is_empty() {
+                &scan.delim_types
+            } else {
+                &converted_from
+            };
+            self.log(1, || {
+                format!(
+                    "converted {}: {} -> {}",
+                    name,
+                    DelimSetFmt(from),
+                    self.target_name(&scan)
+                )
+            });
+        } else if !needs_conversion {
+            self.log(1, || {
+                format!(
+                    "skipping {} (already {})",
+                    name,
+                    DelimSetFmt(&scan.delim_types)
+                )
+            });
+        }
+
+        if !self.do_covert {
+            // Diffs are shown instead for files which need conversion.
+            let few_offenders = self
+                .max_offenders
+                .is_none_or(|max| self.offenders(&scan) <= max);
+            let reported =
+                (needs_conversion && few_offenders && !self.show_diff) || missing_final_newline;
+            if self.format == Format::Text && reported && !self.quiet {
+                let is_tty = if to_stderr {
+                    io::stderr().is_terminal()
+                } else {
+                    stdout().is_terminal()
+                };
+                let record = self.detected_record(name, &scan, first, needs_conversion, is_tty);
+                if to_stderr {
+                    eprint!("{}", record);
+                } else {
+                    print!("{}", record);
+                }
+            }
+        } else if missing_final_newline {
+            warn(format_args!("no final newline: {}", name));
+        }
+
+        match () {
+            () if needs_conversion => Outcome::NeedsConversion(scan.delim_types, converted_from),
+            () if missing_final_newline => Outcome::MissingFinalNewline(scan.delim_types),
+            _ => Outcome::Correct(scan.delim_types),
+        }
+    }
+}
+
+/// Converts or detects a file.
+///
+/// The file is streamed, so its whole contents are never loaded into memory.
+/// UTF-16 files, gzip files, and files with Unicode separators enabled are exceptions
+/// and loaded at once.
+fn convert_file(p: &Path, config: &Config) -> Result<Outcome> {
+    let link_metadata =
+        symlink_metadata(p).with_context(|| format!("reading metadata of {}", PathFmt(p)))?;
+    if link_metadata.file_type().is_symlink() {
+        warn(format_args!("skipping symlink: {}", PathFmt(p)));
+        return Ok(Outcome::Symlink);
+    }
+    if let Some(max) = config.max_file_size {
+        if link_metadata.len() > max {
+            warn(format_args!("skipping large file: {}", PathFmt(p)));
+            return Ok(Outcome::TooLarge);
+        }
+    }
+    // Taken before reading anything, so any later change is detected.
+    let stamp = FileStamp::new(&link_metadata);
+    let outcome = convert_regular_file(p, config, stamp)?;
+    copy_to_out_dir(p, config, stamp, &outcome)?;
+    Ok(outcome)
+}
+
+/// Converts or detects a file read ahead by `--io-bound-jobs` in the same way as `convert_file`,
+/// but from its contents already loaded.
+fn convert_read_ahead(p: &Path, config: &Config, read_ahead: ReadAhead) -> Result<Outcome> {
+    let ReadAhead { stamp, raw } = read_ahead;
+    let head = &raw[..raw.len().min(BINARY_CHECK_LEN)];
+    let encoding = config.encoding.unwrap_or_else(|| Encoding::detect(head));
+    if let Some(outcome) = skip_by_head(p, config, head, encoding) {
+        return Ok(outcome);
+    }
+
+    let outcome = convert_contents(p, config, &raw, encoding, None, stamp)?;
+    copy_to_out_dir(p, config, stamp, &outcome)?;
+    Ok(outcome)
+}
+
+/// Copies a file left as it is under `--out-dir`.
+fn copy_to_out_dir(p: &Path, config: &Config, stamp: FileStamp, outcome: &Outcome) -> Result<()> {
+    // Files already correct are copied as well, so the output directory has the whole tree.
+    if config.do_covert
+        && config.out_dir.is_some()
+        && matches!(
+            outcome,
+            Outcome::Correct(_) | Outcome::MissingFinalNewline(_)
+        )
+    {
+        write_file(p, config, stamp, None, |w| {
+            let mut f = File::open(p).with_context(|| format!("opening file {}", PathFmt(p)))?;
+            io::copy(&mut f, w)?;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Returns the outcome of skipping a file by its `head`, if it's binary or has the marker.
+fn skip_by_head(p: &Path, config: &Config, head: &[u8], encoding: Encoding) -> Option<Outcome> {
+    if !config.process_binary && is_binary(head, encoding) {
+        warn(format_args!("skipping binary file: {}", PathFmt(p)));
+        return Some(Outcome::Binary);
+    }
+    if config.has_marker(head, encoding) {
+        config.log(1, || format!("skipping {} (contains marker)", PathFmt(p)));
+        return Some(Outcome::Marked);
+    }
+    None
+}
+
+/// Converts or detects a file which is not a symlink, whose metadata is `stamp`.
+fn convert_regular_file(p: &Path, config: &Config, stamp: FileStamp) -> Result<Outcome> {
+    if config.zip.is_some() && p.extension().is_some_and(|ext| ext == "zip") {
+        return archive::convert_zip_file(p, config, stamp);
+    }
+    if config.gzip || p.extension().is_some_and(|ext| ext == "gz") {
+        return convert_gzip_file(p, config, stamp);
+    }
+
+    let mut f = File::open(p).with_context(|| format!("opening file {}", PathFmt(p)))?;
+
+    let mut head = Vec::with_capacity(BINARY_CHECK_LEN);
+    (&f).take(BINARY_CHECK_LEN as u64)
+        .read_to_end(&mut head)
+        .with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+    let encoding = config.encoding.unwrap_or_else(|| Encoding::detect(&head));
+
+    if let Some(outcome) = skip_by_head(p, config, &head, encoding) {
+        return Ok(outcome);
+    }
+    let has_bom = encoding == Encoding::Utf8 && head.starts_with(UTF8_BOM);
+    let ending = file_ending(&mut f, has_bom)
+        .with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+    f.rewind()
+        .with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+    let mapped = if config.mmap {
+        map_file(&f, p, stamp)
+    } else {
+        None
+    };
+
+    // Positions of delimiters, regions, tabs and target bytes are found only in loaded contents.
+    if encoding != Encoding::Utf8
+        || config.split.unicode_lineseps
+        || config.split.nel
+        || config.split.trailing_cr_as_text
+        || config.first_offset
+        || config.options.regions.is_some()
+        || config.options.final_newline_only
+        || config.options.replace_tabs.is_some()
+        || config.options.delim_bytes.is_some()
+    {
+        if let Some(mapped) = &mapped {
+            return convert_contents(p, config, mapped, encoding, None, stamp);
+        }
+        let mut file_contents_raw = Vec::new();
+        f.read_to_end(&mut file_contents_raw)
+            .with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+        return convert_contents(p, config, &file_contents_raw, encoding, None, stamp);
+    }
+
+    let r: Box<dyn BufRead> = match &mapped {
+        Some(mapped) => Box::new(&mapped[..]),
+        None => Box::new(BufReader::with_capacity(BUF_SIZE, f)),
+    };
+    let mut scan = Scan {
+        delim_types: BTreeSet::new(),
+        delim_counts: BTreeMap::new(),
+        has_bom,
+        ending,
+        trailing_whitespace: false,
+        tabs: false,
+    };
+    if config.full_scan {
+        scan_stream(r, &mut scan, |_| false)
+    } else if config.do_covert {
+        scan_stream(r, &mut scan, |scan| {
+            // Mixed delimiters decide how files are converted with `--reject-mixed`.
+            config.needs_conversion(scan)
+                && (!(config.warn_mixed || config.reject_mixed) || scan.delim_types.len() > 1)
+        })
+    } else {
+        scan_stream(r, &mut scan, |scan| {
+            scan.delim_types.len() == Delim::ALL.len() && config.needs_conversion(scan)
+        })
+    }
+    .with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+    config.warn_if_mixed(&PathFmt(p), &scan.delim_types);
+
+    if !config.needs_conversion(&scan) {
+        return Ok(config.outcome(&PathFmt(p), scan, None, false, false));
+    }
+
+    let target = config.target_for(&scan);
+    let options = config.options_for(&scan);
+    if config.show_diff && !config.quiet {
+        let raw = read(p).with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+        let file_contents = FileContents::parse(&raw, encoding, &config.split);
+        print!(
+            "{}",
+            config.diff(&PathFmt(p), &file_contents, target, &options)?
+        );
+    }
+    if config.do_covert && !config.confirm(&PathFmt(p), &scan)? {
+        return Ok(Outcome::Declined);
+    }
+    if config.do_covert {
+        let verify = config.verifier(target, Encoding::Utf8, false);
+        write_file(p, config, stamp, verify, |w| {
+            let f = File::open(p).with_context(|| format!("opening file {}", PathFmt(p)))?;
+            let r = BufReader::with_capacity(BUF_SIZE, f);
+            convert_stream(r, w, target, &options)?;
+            Ok(())
+        })?;
+    } else if config.check_writable {
+        check_writable(p, config)?;
+    }
+
+    Ok(config.outcome(&PathFmt(p), scan, None, true, false))
+}
+
+/// Memory-maps `f` for `--mmap`, or returns `None` with a warning for falling back to reading it.
+///
+/// The file is not mapped if its size has changed since `stamp` was taken. Truncating a file while
+/// it's mapped may still crash the process, so it should be used only for files not being written.
+fn map_file(f: &File, p: &Path, stamp: FileStamp) -> Option<Mmap> {
+    // SAFETY: The map is only read, and `--mmap` is only for detect modes, which never write files.
+    match unsafe { Mmap::map(f) } {
+        Ok(mapped) if mapped.len() as u64 == stamp.len => Some(mapped),
+        Ok(_) => {
+            warn(format_args!(
+                "{} changed while mapping it, so it's read instead",
+                PathFmt(p)
+            ));
+            None
+        }
+        Err(e) => {
+            warn(format_args!(
+                "can't map {}, so it's read instead: {}",
+                PathFmt(p),
+                e
+            ));
+            None
+        }
+    }
+}
+
+/// Converts or detects a gzip-compressed file, which is loaded and decompressed at once.
+/// Converted contents are compressed again at the level estimated from the original header.
+fn convert_gzip_file(p: &Path, config: &Config, stamp: FileStamp) -> Result<Outcome> {
+    let raw = read(p).with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+    let mut decompressed = Vec::new();
+    MultiGzDecoder::new(&raw[..])
+        .read_to_end(&mut decompressed)
+        .with_context(|| format!("decompressing gzip file {}", PathFmt(p)))?;
+
+    let encoding = config
+        .encoding
+        .unwrap_or_else(|| Encoding::detect(&decompressed));
+    if !config.process_binary && is_binary(&decompressed, encoding) {
+        warn(format_args!("skipping binary file: {}", PathFmt(p)));
+        return Ok(Outcome::Binary);
+    }
+    if config.has_marker(&decompressed, encoding) {
+        config.log(1, || format!("skipping {} (contains marker)", PathFmt(p)));
+        return Ok(Outcome::Marked);
+    }
+
+    let level = gzip_level(&raw);
+    convert_contents(p, config, &decompressed, encoding, Some(level), stamp)
+}
+
+/// Estimates compression level from the extra flags in gzip header.
+fn gzip_level(raw: &[u8]) -> Compression {
+    match raw.get(8) {
+        Some(2) => Compression::best(),
+        Some(4) => Compression::fast(),
+        _ => Compression::default(),
+    }
+}
+
+/// Converts or detects a file whose contents are loaded at once.
+/// With `gzip`, contents are written compressed at the level.
+/// `stamp` is of the file before it was read.
+fn convert_contents(
+    p: &Path,
+    config: &Config,
+    raw: &[u8],
+    encoding: Encoding,
+    gzip: Option<Compression>,
+    stamp: FileStamp,
+) -> Result<Outcome> {
+    let file_contents = FileContents::parse(raw, encoding, &config.split);
+
+    let scan = file_contents.scan_with(&config.options);
+    config.warn_if_mixed(&PathFmt(p), &scan.delim_types);
+    if !config.contents_need_conversion(&file_contents, &scan) {
+        return Ok(config.outcome(&PathFmt(p), scan, None, false, false));
+    }
+
+    let target = config.target_for(&scan);
+    let options = config.options_for(&scan);
+    let first = config.first_offending(&file_contents, target);
+    if config.show_diff && !config.quiet {
+        print!(
+            "{}",
+            config.diff(&PathFmt(p), &file_contents, target, &options)?
+        );
+    }
+    if config.do_covert && !config.confirm(&PathFmt(p), &scan)? {
+        return Ok(Outcome::Declined);
+    }
+    if config.do_covert {
+        let verify = config.verifier(target, encoding, gzip.is_some());
+        write_file(p, config, stamp, verify, |w| match gzip {
+            Some(level) => {
+                let mut w = GzEncoder::new(w, level);
+                file_contents.write_to(&mut w, target, &options)?;
+                w.finish()?;
+                Ok(())
+            }
+            None => {
+                file_contents.write_to(w, target, &options)?;
+                Ok(())
+            }
+        })?;
+    } else if config.check_writable {
+        check_writable(p, config)?;
+    }
+
+    Ok(config.outcome(&PathFmt(p), scan, first, true, false))
+}
+
+/// Replaces contents of `p` with ones written by `write`, through a temporary file.
+/// With `--check-unchanged`, it's left if it differs from `stamp` just before replacing.
+/// With `--out-dir`, the file under it is written instead, and `p` is left as it is.
+fn write_file(
+    p: &Path,
+    config: &Config,
+    stamp: FileStamp,
+    verify: Option<Verify>,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    let metadata = metadata(p).with_context(|| format!("reading metadata of {}", PathFmt(p)))?;
+    let out_path = config
+        .out_dir
+        .as_ref()
+        .map(|dir| out_path(dir, p))
+        .transpose()?;
+    if let Some(parent) = out_path.as_ref().and_then(|out| out.parent()) {
+        create_dir_all(parent)
+            .with_context(|| format!("creating output directory {}", PathFmt(parent)))?;
+    }
+    let dest = out_path.as_deref().unwrap_or(p);
+
+    let backup_path = config.backup.as_ref().map(|b| b.path(p));
+    if let (Some(backup), Some(backup_path)) = (&config.backup, &backup_path) {
+        if !backup.overwrite && backup_path.exists() {
+            return Err(Error::msg(format!(
+                "backup file already exists: {}",
+                PathFmt(backup_path)
+            )));
+        }
+    }
+
+    let (tmp, f) = create_tmp_file(dest, config.temp_dir.as_deref(), &config.temp_suffix)
+        .with_context(|| format!("creating tmporary file for {}", PathFmt(dest)))?;
+    let tmp_path = tmp.path();
+    let mut f = BufWriter::with_capacity(BUF_SIZE, f);
+    config.log(2, || {
+        format!("writing temporary file {}", PathFmt(tmp_path))
+    });
+
+    write(&mut f).with_context(|| {
+        format!(
+            "writing file contents to tmporary file: {}",
+            PathFmt(tmp_path)
+        )
+    })?;
+
+    f.flush().with_context(|| {
+        format!(
+            "writing file contents to tmporary file: {}",
+            PathFmt(tmp_path)
+        )
+    })?;
+
+    // Changing owner and writing may clear setuid and setgid bits, so the full mode including them
+    // is set after both.
+    if let Err(e) = copy_owner(f.get_ref(), &metadata) {
+        warn(format_args!("can't keep owner of {}: {}", PathFmt(p), e));
+    }
+    f.get_ref()
+        .set_permissions(metadata.permissions())
+        .with_context(|| {
+            format!(
+                "setting permissions of tmporary file: {}",
+                PathFmt(tmp_path)
+            )
+        })?;
+    let mtime = if config.preserve_mtime {
+        let mtime = metadata
+            .modified()
+            .with_context(|| format!("reading modified time of {}", PathFmt(p)))?;
+        Some(mtime)
+    } else {
+        None
+    };
+    if let Some(mtime) = mtime {
+        f.get_ref().set_modified(mtime).with_context(|| {
+            format!(
+                "setting modified time of tmporary file: {}",
+                PathFmt(tmp_path)
+            )
+        })?;
+    }
+    drop(f);
+
+    let staged = StagedFile {
+        tmp,
+        path: dest.to_path_buf(),
+        original: p.to_path_buf(),
+        backup_path,
+        mtime,
+        cross_dir: config.temp_dir.is_some(),
+        // Renaming would detach this name from the other links, which keep the old contents.
+        in_place: config.inplace_rewrite && out_path.is_none() && link_count(&metadata) > 1,
+        log: config.verbose >= 2,
+        verify,
+        unchanged: Some(stamp).filter(|_| config.check_unchanged),
+    };
+    match &config.transaction {
+        Some(transaction) => {
+            transaction
+                .lock()
+                .expect("transaction lock is not poisoned")
+                .push(staged);
+            Ok(())
+        }
+        None => staged.commit(),
+    }
+}
+
+/// Check of delimiters in a converted file by `--verify`.
+#[derive(Debug)]
+struct Verify {
+    /// Delimiters allowed after conversion.
+    allowed: BTreeSet<Delim>,
+    /// With `--final-newline-only`, the delimiter required only at the end instead of `allowed`.
+    final_delim: Option<Delim>,
+    encoding: Encoding,
+    gzip: bool,
+    split: SplitOptions,
+    /// Delimiters in regions are not checked.
+    regions: Option<Regions>,
+}
+
+impl Verify {
+    /// Reads `path` again, and checks that it contains only allowed delimiters.
+    fn check(&self, path: &Path) -> Result<()> {
+        let mut raw = read(path).context("reading converted file")?;
+        if self.gzip {
+            let mut decompressed = Vec::new();
+            MultiGzDecoder::new(&raw[..])
+                .read_to_end(&mut decompressed)
+                .context("decompressing converted file")?;
+            raw = decompressed;
+        }
+
+        let options = Options {
+            regions: self.regions.clone(),
+            ..Options::default()
+        };
+        let contents = FileContents::parse(&raw, self.encoding, &self.split);
+        if let Some(delim) = self.final_delim {
+            if !contents.has_single_final_delim(delim) {
+                return Err(Error::msg(format!(
+                    "converted file doesn't end with a single {}",
+                    delim
+                )));
+            }
+            return Ok(());
+        }
+        let delim_types = contents.scan_with(&options).delim_types;
+        if !delim_types.is_subset(&self.allowed) {
+            return Err(Error::msg(format!(
+                "converted file contains {}",
+                DelimSetFmt(&delim_types)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Changes owner and group of `f` to those in `metadata`, if they differ.
+/// It usually needs root privileges.
+#[cfg(unix)]
+fn copy_owner(f: &File, metadata: &Metadata) -> io::Result<()> {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    let current = f.metadata()?;
+    if current.uid() == metadata.uid() && current.gid() == metadata.gid() {
+        return Ok(());
+    }
+    fchown(f, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_f: &File, _metadata: &Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+/// Returns the number of hard links of a file.
+#[cfg(unix)]
+fn link_count(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink()
+}
+
+/// Link counts are unavailable on stable Rust except Unix, so files are regarded as not linked.
+#[cfg(not(unix))]
+fn link_count(_metadata: &Metadata) -> u64 {
+    1
+}
+
+/// Checks that `write_file` could replace `p`, by creating and removing temporary files.
+///
+/// With `temp_dir`, `p` may also be replaced by copying over it, so it's enough if either
+/// its directory or itself is writable, unless a backup is created next to it.
+fn check_writable(p: &Path, config: &Config) -> Result<()> {
+    let probe = |temp_dir: Option<&Path>| -> io::Result<()> {
+        let (tmp, f) = create_tmp_file(p, temp_dir, &config.temp_suffix)?;
+        drop(f);
+        tmp.remove()
+    };
+    let not_writable = || format!("{} can't be replaced", PathFmt(p));
+
+    if let Some(temp_dir) = &config.temp_dir {
+        probe(Some(temp_dir))
+            .with_context(|| format!("creating temporary file in {}", PathFmt(temp_dir)))
+            .with_context(not_writable)?;
+    }
+    match probe(None) {
+        Ok(()) => Ok(()),
+        Err(_) if config.temp_dir.is_some() && config.backup.is_none() => OpenOptions::new()
+            .write(true)
+            .open(p)
+            .map(drop)
+            .with_context(|| format!("opening {} for writing", PathFmt(p)))
+            .with_context(not_writable),
+        Err(e) => Err(e)
+            .context("creating temporary file in the same directory")
+            .with_context(not_writable),
+    }
+}
+
+/// A temporary file with converted contents, which is waiting to replace the original.
+/// It's removed if dropped without being committed.
+#[derive(Debug)]
+struct StagedFile {
+    tmp: TempPath,
+    path: PathBuf,
+    /// Original file, which differs from `path` with `--out-dir`.
+    original: PathBuf,
+    backup_path: Option<PathBuf>,
+    mtime: Option<SystemTime>,
+    cross_dir: bool,
+    /// Copy contents over the original file instead of renaming, keeping its inode.
+    in_place: bool,
+    /// Log each step to stderr.
+    log: bool,
+    verify: Option<Verify>,
+    /// Stamp which the original file must still match when it's replaced.
+    unchanged: Option<FileStamp>,
+}
+
+impl StagedFile {
+    /// Fails if the original file has changed since it was read, with `--check-unchanged`.
+    fn check_unchanged(&self) -> Result<()> {
+        match &self.unchanged {
+            Some(stamp) => stamp.check(&self.original),
+            None => Ok(()),
+        }
+    }
+
+    /// Copies the backup if required, and replaces the original file.
+    fn commit(mut self) -> Result<()> {
+        self.check_unchanged()?;
+        if let Some(backup_path) = &self.backup_path {
+            if self.log {
+                eprintln!(
+                    "copying backup {} -> {}",
+                    PathFmt(&self.path),
+                    PathFmt(backup_path)
+                );
+            }
+            copy(&self.path, backup_path).with_context(|| {
+                format!(
+                    "copying backup file: {} => {}",
+                    PathFmt(&self.path),
+                    PathFmt(backup_path)
+                )
+            })?;
+        }
+
+        if self.in_place {
+            if self.log {
+                eprintln!(
+                    "rewriting {} in place from {}",
+                    PathFmt(&self.path),
+                    PathFmt(self.tmp.path())
+                );
+            }
+            copy_over(self.tmp.path(), &self.path, self.mtime).with_context(|| {
+                format!(
+                    "rewriting file in place: {} => {}",
+                    PathFmt(self.tmp.path()),
+                    PathFmt(&self.path)
+                )
+            })?;
+        } else {
+            if self.log {
+                eprintln!(
+                    "renaming {} -> {}",
+                    PathFmt(self.tmp.path()),
+                    PathFmt(&self.path)
+                );
+            }
+            replace_file(self.tmp.path(), &self.path, self.mtime, self.cross_dir).with_context(
+                || {
+                    format!(
+                        "renaming temporary file: {} => {}",
+                        PathFmt(self.tmp.path()),
+                        PathFmt(&self.path)
+                    )
+                },
+            )?;
+        }
+        self.tmp.release();
+
+        if let Some(verify) = &self.verify {
+            verify
+                .check(&self.path)
+                .with_context(|| format!("verifying {}", PathFmt(&self.path)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces original files with all staged files of `--transaction`,
+/// or removes them all if `outcomes` is an error.
+///
+/// If replacing fails in the middle, the files already replaced are not rolled back.
+fn finish_transaction(staged: Vec<StagedFile>, outcomes: &Result<Vec<Outcome>>) -> Result<()> {
+    // Temporary files not committed are removed as they are dropped.
+    if outcomes.is_err() {
+        return Ok(());
+    }
+    // All originals are checked first, so that a changed one leaves every file as it is.
+    for f in &staged {
+        f.check_unchanged()?;
+    }
+    for f in staged {
+        f.commit()?;
+    }
+    Ok(())
+}
+
+/// Returns the path under `--out-dir` `dir` for `p`, which is its path relative to the current
+/// directory. Paths outside the current directory can't be placed under it.
+fn out_path(dir: &Path, p: &Path) -> Result<PathBuf> {
+    let cwd = current_dir().context("getting current directory")?;
+    let relative = display_relative(p, &cwd, &cwd);
+    if relative.is_absolute() || relative.starts_with("..") {
+        return Err(Error::msg(format!(
+            "{} is outside the current directory, so it can't be written under '--out-dir'",
+            PathFmt(p)
+        )));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Size and modified time of a file, to find changes by others for `--check-unchanged`.
+///
+/// Changes keeping both, like a rewrite of the same length within the timestamp resolution,
+/// are not found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn new(metadata: &Metadata) -> FileStamp {
+        FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    /// Fails if `path` doesn't match the stamp anymore.
+    fn check(&self, path: &Path) -> Result<()> {
+        let metadata =
+            metadata(path).with_context(|| format!("reading metadata of {}", PathFmt(path)))?;
+        if FileStamp::new(&metadata) != *self {
+            return Err(Error::msg(format!(
+                "{} was changed while converting it, so it's left as it is",
+                PathFmt(path)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Replaces `dst` with `src` by renaming.
+///
+/// If they are on different devices, copies contents of `src` over `dst` by `copy_over` instead.
+/// With `cross_dir`, `src` is in another directory, so copying is also tried on any rename error,
+/// e.g. because the directory of `dst` is read-only.
+///
+/// On Windows, renaming is retried for a while if `dst` is used by another process,
+/// and then copying is tried, which succeeds if the process allows writing but not deleting.
+fn replace_file(
+    src: &Path,
+    dst: &Path,
+    mtime: Option<SystemTime>,
+    cross_dir: bool,
+) -> io::Result<()> {
+    match rename_retrying(src, dst) {
+        Err(e) if cross_dir || e.kind() == io::ErrorKind::CrossesDevices || is_transient(&e) => {}
+        res => return res,
+    }
+    copy_over(src, dst, mtime)
+}
+
+/// Copies contents of `src` over `dst` by truncating and writing it, and removes `src`.
+///
+/// `dst` keeps its inode, so its permissions and hard links, and `mtime` is set to it if given.
+/// If `dst` doesn't exist, e.g. under `--out-dir`, it's created with the permissions of `src`.
+/// It's not atomic, so `dst` is partially written if it fails in the middle.
+fn copy_over(src: &Path, dst: &Path, mtime: Option<SystemTime>) -> io::Result<()> {
+    // Writing clears setuid and setgid bits, so permissions are set again after it.
+    let (permissions, create) = match metadata(dst) {
+        Ok(m) => (m.permissions(), false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (metadata(src)?.permissions(), true),
+        Err(e) => return Err(e),
+    };
+    let mut r = File::open(src)?;
+    let mut w = match OpenOptions::new()
+        .write(true)
+        .create(create)
+        .truncate(true)
+        .open(dst)
+    {
+        Err(e) if is_locked(&e) => {
+            return Err(io::Error::new(
+                e.kind(),
+                format!("{} is locked by another process: {}", PathFmt(dst), e),
+            ))
+        }
+        res => res?,
+    };
+    io::copy(&mut r, &mut w)?;
+    w.sync_all()?;
+    w.set_permissions(permissions)?;
+    if let Some(mtime) = mtime {
+        w.set_modified(mtime)?;
+    }
+    drop(w);
+    drop(r);
+
+    remove_file(src)
+}
+
+/// Renames `src` to `dst`, retrying with backoff while `dst` is locked by another process.
+fn rename_retrying(src: &Path, dst: &Path) -> io::Result<()> {
+    const RETRY_DELAYS: [Duration; 5] = [
+        Duration::from_millis(10),
+        Duration::from_millis(50),
+        Duration::from_millis(100),
+        Duration::from_millis(250),
+        Duration::from_millis(500),
+    ];
+
+    let mut delays = RETRY_DELAYS.iter();
+    loop {
+        match rename(src, dst) {
+            Err(e) if is_transient(&e) => match delays.next() {
+                Some(&delay) => sleep(delay),
+                None => return Err(e),
+            },
+            res => return res,
+        }
+    }
+}
+
+/// Checks whether `e` is caused by a file opened by another process, which can happen on Windows.
+#[cfg(windows)]
+fn is_locked(e: &io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    matches!(
+        e.raw_os_error(),
+        Some(ERROR_SHARING_VIOLATION) | Some(ERROR_LOCK_VIOLATION)
+    )
+}
+
+#[cfg(not(windows))]
+fn is_locked(_e: &io::Error) -> bool {
+    false
+}
+
+/// Checks whether `e` may go away by retrying, which is when the file is locked, or access is
+/// denied on Windows, as it's reported for files pending deletion or scanned by anti-virus software.
+#[cfg(windows)]
+fn is_transient(e: &io::Error) -> bool {
+    const ERROR_ACCESS_DENIED: i32 = 5;
+
+    is_locked(e) || e.raw_os_error() == Some(ERROR_ACCESS_DENIED)
+}
+
+#[cfg(not(windows))]
+fn is_transient(_e: &io::Error) -> bool {
+    false
+}
+
+/// Converts stdin to stdout, or detects stdin and reports to stderr.
+fn convert_stdin(config: &Config) -> Result<Outcome> {
+    let name = config.stdin_name.as_str();
+    let stdin = stdin();
+    let mut input = stdin.lock();
+    // The head is enough for detection and checks, so the rest may be streamed.
+    let mut file_contents_raw = Vec::with_capacity(BINARY_CHECK_LEN);
+    if config.line_buffered {
+        // Waiting for a full head would hold back the first lines, so only what's available is checked.
+        let available = input.fill_buf().context("reading contents from stdin")?;
+        let len = available.len().min(BINARY_CHECK_LEN);
+        file_contents_raw.extend_from_slice(&available[..len]);
+        input.consume(len);
+    } else {
+        (&mut input)
+            .take(BINARY_CHECK_LEN as u64)
+            .read_to_end(&mut file_contents_raw)
+            .context("reading contents from stdin")?;
+    }
+
+    let encoding = config
+        .encoding
+        .unwrap_or_else(|| Encoding::detect(&file_contents_raw));
+
+    // Skipped contents are passed through unchanged, so that nothing is lost in a pipeline.
+    if !config.process_binary && is_binary(&file_contents_raw, encoding) {
+        warn(format_args!("skipping binary input: {}", name));
+        if config.do_covert {
+            pass_through_stdin(&file_contents_raw, &mut input)?;
+        }
+        return Ok(Outcome::Binary);
+    }
+    // Marked contents are passed through unchanged, as they must never be reformatted.
+    if config.has_marker(&file_contents_raw, encoding) {
+        config.log(1, || format!("skipping {} (contains marker)", name));
+        if config.do_covert {
+            pass_through_stdin(&file_contents_raw, &mut input)?;
+        }
+        return Ok(Outcome::Marked);
+    }
+
+    if config.streams_stdin(encoding) {
+        let mut scan = Scan::default();
+        let stdout = stdout();
+        let mut w = BufWriter::with_capacity(BUF_SIZE, stdout.lock());
+        let r = (&file_contents_raw[..]).chain(input);
+        convert_stream_with(
+            r,
+            &mut w,
+            config.target_delim,
+            &config.options,
+            &mut scan,
+            config.line_buffered,
+        )
+        .context("converting contents from stdin to stdout")?;
+        w.flush().context("writing contents to stdout")?;
+
+        config.warn_if_mixed(&name, &scan.delim_types);
+        let needs_conversion = config.needs_conversion(&scan);
+        return Ok(config.outcome(&name, scan, None, needs_conversion, true));
+    }
+    input
+        .read_to_end(&mut file_contents_raw)
+        .context("reading contents from stdin")?;
+
+    // Skips splitting into lines if contents are passed through unchanged, unless their counts are needed.
+    if encoding == Encoding::Utf8 && config.only_delims() && config.histogram.is_none() {
+        let delim_types = scan_delims(&file_contents_raw);
+        // Without counts, the dominant delimiter is known only if there is at most one type.
+        let passed = if config.auto_to {
+            delim_types.len() <= 1
+        } else if !config.accepted.is_empty() {
+            delim_types.len() <= 1 && delim_types.is_subset(&config.accepted)
+        } else {
+            delim_types.is_subset(&config.target_delim_set)
+                && (!config.reject_mixed || delim_types.len() <= 1)
+        };
+        if passed {
+            config.warn_if_mixed(&name, &delim_types);
+            if config.do_covert {
+                let stdout = stdout();
+                let mut w = stdout.lock();
+                w.write_all(&file_contents_raw)
+                    .and_then(|_| w.flush())
+                    .context("writing contents to stdout")?;
+            }
+            return Ok(Outcome::Correct(delim_types));
+        }
+    }
+
+    let file_contents = FileContents::parse(&file_contents_raw, encoding, &config.split);
+    let scan = file_contents.scan_with(&config.options);
+    config.warn_if_mixed(&name, &scan.delim_types);
+    let needs_conversion = config.contents_need_conversion(&file_contents, &scan);
+    let target = config.target_for(&scan);
+    let options = config.options_for(&scan);
+    let first = config.first_offending(&file_contents, target);
+
+    if config.do_covert {
+        let stdout = stdout();
+        let mut w = BufWriter::with_capacity(BUF_SIZE, stdout.lock());
+        file_contents
+            .write_to(&mut w, target, &options)
+            .context("writing contents to stdout")?;
+        w.flush().context("writing contents to stdout")?;
+    } else if config.show_diff && needs_conversion && !config.quiet {
+        eprint!("{}", config.diff(&name, &file_contents, target, &options)?);
+    }
+
+    Ok(config.outcome(&name, scan, first, needs_conversion, true))
+}
+
+/// Writes `head` already read from stdin and the rest of `input` to stdout as they are.
+fn pass_through_stdin(head: &[u8], input: &mut impl Read) -> Result<()> {
+    let stdout = stdout();
+    let mut w = stdout.lock();
+    w.write_all(head)
+        .and_then(|_| io::copy(input, &mut w))
+        .and_then(|_| w.flush())
+        .context("writing contents to stdout")?;
+    Ok(())
+}
+
+/// Formats changed lines between `before` and `after` as unified-diff-like hunks.
+///
+/// Lines are compared one by one, because conversion never moves text across lines.
+fn diff_lines(name: &dyn fmt::Display, before: &FileContents, after: &FileContents) -> String {
+    let line_at = |contents: &'_ FileContents, i: usize| {
+        contents
+            .lines
+            .get(i)
+            .filter(|l| !l.is_empty_last())
+            .cloned()
+    };
+
+    let mut out = format!("--- {}\n+++ {}\n", name, name);
+    let len = before.lines.len().max(after.lines.len());
+    let mut i = 0;
+    while i < len {
+        if line_at(before, i) == line_at(after, i) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < len && line_at(before, i) != line_at(after, i) {
+            i += 1;
+        }
+        let removed: Vec<Line> = (start..i).filter_map(|j| line_at(before, j)).collect();
+        let added: Vec<Line> = (start..i).filter_map(|j| line_at(after, j)).collect();
+
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            start + 1,
+            removed.len(),
+            start + 1,
+            added.len()
+        ));
+        for l in &removed {
+            out.push_str(&format!("-{}\n", VisibleLine(l, before.encoding)));
+        }
+        for l in &added {
+            out.push_str(&format!("+{}\n", VisibleLine(l, after.encoding)));
+        }
+    }
+    out
+}
+
+/// Finds how contents of `f` end, by reading its last byte.
+fn file_ending(f: &mut File, has_bom: bool) -> io::Result<Ending> {
+    let len = f.seek(SeekFrom::End(0))?;
+    let bom_len = if has_bom { UTF8_BOM.len() as u64 } else { 0 };
+    if len <= bom_len {
+        return Ok(Ending::Empty);
+    }
+
+    f.seek(SeekFrom::End(-1))?;
+    let mut last = [0u8];
+    f.read_exact(&mut last)?;
+    if last[0] == b'\r' || last[0] == b'\n' {
+        Ok(Ending::Delim)
+    } else {
+        Ok(Ending::Text)
+    }
+}
+
+/// Finds the most common delimiter of the first file for `--match-first`, which is LF on ties or
+/// if it has no delimiters.
+fn first_file_target(p: &Path, config: &Config) -> Result<Delim> {
+    let raw = read(p).with_context(|| format!("reading file contents of {}", PathFmt(p)))?;
+    let encoding = config.encoding.unwrap_or_else(|| Encoding::detect(&raw));
+    let scan = FileContents::parse(&raw, encoding, &config.split).scan();
+    let target = scan.dominant_delim().unwrap_or(Delim::LF);
+    if config.options.forbidden.contains(&target) {
+        return Err(Error::msg(format!(
+            "The target {} taken from {} is forbidden by '--forbid'.",
+            target,
+            PathFmt(p)
+        )));
+    }
+    config.log(1, || format!("target {} taken from {}", target, PathFmt(p)));
+    Ok(target)
+}
+
+/// Replaces symlinks in `inputs` with their targets, for `--follow-symlinks`.
+/// Files reached through multiple paths are kept only once, so that they are not converted twice.
+fn resolve_symlinks(inputs: Vec<Input>) -> Vec<Input> {
+    let mut seen = HashSet::new();
+    let mut resolved = Vec::with_capacity(inputs.len());
+
+    for input in inputs {
+        let p = match input {
+            Input::Stdin => {
+                resolved.push(input);
+                continue;
+            }
+            Input::File(p) => p,
+        };
+
+        let real_path = match canonicalize(&p) {
+            Ok(real_path) => real_path,
+            Err(e) => {
+                warn(format_args!("skipping {}: {}", PathFmt(&p), e));
+                continue;
+            }
+        };
+        if !seen.insert(real_path.clone()) {
+            continue;
+        }
+
+        let is_symlink = symlink_metadata(&p).is_ok_and(|m| m.file_type().is_symlink());
+        if is_symlink {
+            resolved.push(Input::File(real_path));
+        } else {
+            resolved.push(Input::File(p));
+        }
+    }
+
+    resolved
+}
+
+/// Number of threads reading files per CPU with `--io-bound-jobs`.
+///
+/// They mostly wait for reads on high-latency storage, so more of them keep it busy.
+const IO_BOUND_JOBS_PER_CPU: usize = 8;
+
+/// Returns the number of threads reading files ahead of jobs, if `--io-bound-jobs` is given.
+fn io_bound_jobs(matches: &ArgMatches<'_>, project: &ProjectConfig) -> Option<usize> {
+    if !matches.is_present("io-bound-jobs") && !project.io_bound_jobs {
+        return None;
+    }
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    Some(cpus * IO_BOUND_JOBS_PER_CPU)
+}
+
+/// Returns the number of jobs, or 0 for the number of CPUs.
+/// `--jobs` on the command line takes precedence over the config.
+fn job_count(matches: &ArgMatches<'_>, project: &ProjectConfig) -> Result<usize> {
+    match matches.value_of("jobs") {
+        Some(s) => s
+            .parse::<usize>()
+            .with_context(|| format!("invalid number of jobs: {:?}", s)),
+        None => Ok(project.jobs.unwrap_or(0)),
+    }
+}
+
+/// A file read by a thread of `--io-bound-jobs`, before a job converts it.
+#[derive(Debug)]
+struct ReadAhead {
+    stamp: FileStamp,
+    raw: Vec<u8>,
+}
+
+impl ReadAhead {
+    /// Files larger than this are left to jobs, which stream them instead of holding them in memory.
+    const MAX_LEN: u64 = 16 * 1024 * 1024;
+
+    /// Reads `p` if it's converted from loaded contents, or returns `None` to leave it to
+    /// `convert_file`, which also reports errors.
+    fn read(p: &Path, config: &Config) -> Option<ReadAhead> {
+        let metadata = symlink_metadata(p).ok()?;
+        let left = !metadata.is_file()
+            || metadata.len() > ReadAhead::MAX_LEN
+            || config.max_file_size.is_some_and(|max| metadata.len() > max)
+            || config.mmap
+            || config.gzip
+            || p.extension().is_some_and(|ext| ext == "gz" || ext == "zip");
+        if left {
+            return None;
+        }
+        // Taken before reading, so any later change is detected as with `convert_file`.
+        let stamp = FileStamp::new(&metadata);
+        let raw = read(p).ok()?;
+        Some(ReadAhead { stamp, raw })
+    }
+}
+
+/// Processes `inputs` by `process` in the current thread pool for `--io-bound-jobs`,
+/// with `readers` threads reading files ahead of it.
+///
+/// Read files are passed through a bounded channel, so reads overlap with each other and with
+/// conversion, while at most `readers` of them wait in memory. Outcomes are in the order of `inputs`.
+fn process_read_ahead(
+    inputs: &[Input],
+    config: &Config,
+    readers: usize,
+    process: impl Fn(usize, Option<ReadAhead>) -> Result<Outcome> + Sync,
+) -> Result<Vec<Outcome>> {
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = sync_channel(readers);
+    scope(|s| {
+        for _ in 0..readers {
+            let (tx, next) = (tx.clone(), &next);
+            s.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let read_ahead = match inputs.get(i) {
+                    Some(Input::File(p)) => ReadAhead::read(p, config),
+                    Some(Input::Stdin) => None,
+                    None => return,
+                };
+                // The receiver is dropped when processing stops by an error.
+                if tx.send((i, read_ahead)).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(tx);
+
+        let mut outcomes = rx
+            .into_iter()
+            .par_bridge()
+            .map(|(i, read_ahead)| Ok((i, process(i, read_ahead)?)))
+            .collect::<Result<Vec<(usize, Outcome)>>>()?;
+        outcomes.sort_by_key(|&(i, _)| i);
+        Ok(outcomes.into_iter().map(|(_, outcome)| outcome).collect())
+    })
+}
+
+/// Returns delimiters left as they are with `--from` or `--forbid`, which are all delimiters other than them.
+fn kept_delims(from: Option<Delim>, forbidden: &BTreeSet<Delim>) -> BTreeSet<Delim> {
+    if from.is_none() && forbidden.is_empty() {
+        return BTreeSet::new();
+    }
+    Delim::ALL
+        .iter()
+        .chain(Delim::UNICODE.iter())
+        .copied()
+        .filter(|&d| from != Some(d) && !forbidden.contains(&d))
+        .collect()
+}
+
+/// Parses a number of bytes, optionally followed by `K`, `M` or `G` for powers of 1024.
+fn parse_size(s: &str) -> Result<u64> {
+    let invalid = || {
+        format!(
+            "invalid size {:?} (expected a number like 500K, 50M or 1G)",
+            s
+        )
+    };
+    let (digits, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_uppercase()),
+        _ => (s, 'B'),
+    };
+    let shift = match unit {
+        'B' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        _ => return Err(Error::msg(invalid())),
+    };
+    let n = digits.parse::<u64>().with_context(invalid)?;
+    n.checked_mul(1 << shift)
+        .ok_or_else(|| Error::msg(invalid()))
+}
+
+/// Parses hex bytes like `0A 0D`, where whitespace between bytes is optional.
+fn parse_hex_bytes(s: &str) -> Result<Vec<u8>> {
+    let digits: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+    let valid = digits.iter().all(char::is_ascii_hexdigit);
+    if !valid || digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(Error::msg(format!(
+            "invalid hex bytes {:?} (expected pairs of hex digits like '0A 0D')",
+            s
+        )));
+    }
+    let bytes = digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).expect("pair is valid hex digits")
+        })
+        .collect();
+    Ok(bytes)
+}
+
+/// Reads a list of paths for `--files-from` from file `source`, or stdin if it's `-`.
+fn read_file_list(source: &str, null: bool) -> Result<Vec<PathBuf>> {
+    let mut raw = Vec::new();
+    if source == STDIN_PATTERN {
+        stdin()
+            .lock()
+            .read_to_end(&mut raw)
+            .context("reading file list from stdin")?;
+    } else {
+        File::open(source)
+            .and_then(|mut f| f.read_to_end(&mut raw))
+            .with_context(|| format!("reading file list {:?}", source))?;
+    }
+    parse_file_list(&raw, null).with_context(|| format!("parsing file list {:?}", source))
+}
+
+/// Splits a list of literal paths by newline, or by NUL if `null`.
+/// Empty entries are skipped, and CR at the end of lines is removed.
+fn parse_file_list(raw: &[u8], null: bool) -> Result<Vec<PathBuf>> {
+    let sep = if null { b'\0' } else { b'\n' };
+    raw.split(|&b| b == sep)
+        .map(|entry| {
+            if null {
+                entry
+            } else {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+/// Paths in file lists and git output are raw bytes on Unix, and must be UTF-8 elsewhere.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(PathBuf::from(OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf> {
+    let s = std::str::from_utf8(bytes)
+        .with_context(|| format!("non-UTF-8 path: {:?}", String::from_utf8_lossy(bytes)))?;
+    Ok(PathBuf::from(s))
+}
+
+/// Lists paths matching `pat`, leaving out ones ignored by `ignore_filter` if it's a wildcard.
+/// Explicitly named paths are processed even if ignored.
+fn glob_paths(
+    pat: &str,
+    ignore_filter: Option<&mut IgnoreFilter>,
+    hidden: bool,
+) -> Result<Vec<PathBuf>> {
+    let is_wildcard = pat.contains(&['*', '?', '['][..]);
+    let mut ignore_filter = ignore_filter.filter(|_| is_wildcard);
+
+    let options = MatchOptions {
+        require_literal_leading_dot: !hidden,
+        ..MatchOptions::new()
+    };
+    let pathes =
+        glob_with(pat, options).with_context(|| format!("listing files for pattern: {:?}", pat))?;
+    let mut matched = Vec::new();
+    for p in pathes {
+        let p = p.with_context(|| format!("reading path in {:?}", pat))?;
+        if let Some(filter) = &mut ignore_filter {
+            if filter.is_ignored(&p, p.is_dir()) {
+                continue;
+            }
+        }
+        matched.push(p);
+    }
+    Ok(matched)
+}
+
+/// Lists regular files under `dir` recursively.
+/// Unreadable directories are reported and skipped, and symlinked directories are not followed.
+/// Paths matched by `ignore_filter` are skipped, and so are hidden ones unless `hidden`.
+/// Broken symlinks are skipped too, with a warning if `warn_broken`.
+fn walk_dir(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+    mut ignore_filter: Option<&mut IgnoreFilter>,
+    hidden: bool,
+    warn_broken: bool,
+) {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn(format_args!("skipping directory {}: {}", PathFmt(dir), e));
+            return;
+        }
+    };
+
+    let mut entries: Vec<DirEntry> = entries
+        .filter_map(|e| match e {
+            Ok(e) => Some(e),
+            Err(e) => {
+                warn(format_args!("skipping entry in {}: {}", PathFmt(dir), e));
+                None
+            }
+        })
+        .collect();
+    entries.sort_by_key(|e| e.file_name());
+
+    for e in entries {
+        // Same as wildcards without `--hidden`, which don't match leading dots.
+        if !hidden && e.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = e.path();
+        let file_type = e.file_type();
+        if let (Some(filter), Ok(t)) = (ignore_filter.as_deref_mut(), &file_type) {
+            if filter.is_ignored(&path, t.is_dir()) {
+                continue;
+            }
+        }
+
+        match file_type {
+            Ok(t) if t.is_dir() => walk_dir(
+                &path,
+                files,
+                ignore_filter.as_deref_mut(),
+                hidden,
+                warn_broken,
+            ),
+            Ok(t) if t.is_file() => files.push(path),
+            Ok(t) if t.is_symlink() && path.is_file() => files.push(path),
+            Ok(t) if t.is_symlink() && warn_broken && !path.exists() => {
+                warn(format_args!("skipping broken symlink: {}", PathFmt(&path)))
+            }
+            Ok(_) => {}
+            Err(e) => warn(format_args!("skipping {}: {}", PathFmt(&path), e)),
+        }
+    }
+}
+
+/// Checks whether `p` is a symlink whose target doesn't exist.
+fn is_broken_symlink(p: &Path) -> bool {
+    symlink_metadata(p).is_ok_and(|m| m.file_type().is_symlink()) && !p.exists()
+}
+
+/// Filters files by `--include` and `--exclude` globs.
+///
+/// Globs containing `/` are matched against the whole path, and others against the file name.
+#[derive(Debug)]
+struct PathFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+    fn new<'a>(
+        include: impl IntoIterator<Item = &'a str>,
+        exclude: impl IntoIterator<Item = &'a str>,
+    ) -> Result<PathFilter> {
+        let compile = |pat: &str| {
+            Pattern::new(pat).with_context(|| format!("invalid glob pattern: {:?}", pat))
+        };
+        Ok(PathFilter {
+            include: include.into_iter().map(compile).collect::<Result<_>>()?,
+            exclude: exclude.into_iter().map(compile).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Checks whether `path` matches any include glob, if given, and no exclude glob.
+    fn is_match(&self, path: &Path) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|pat| Self::matches(pat, path));
+        included && !self.exclude.iter().any(|pat| Self::matches(pat, path))
+    }
+
+    fn matches(pat: &Pattern, path: &Path) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        if pat.as_str().contains('/') {
+            let path: PathBuf = path
+                .components()
+                .filter(|c| *c != Component::CurDir)
+                .collect();
+            pat.matches_path_with(&path, options)
+        } else {
+            path.file_name()
+                .is_some_and(|name| pat.matches_with(&name.to_string_lossy(), options))
+        }
+    }
+}
+
+/// Matches paths against `.gitignore` and `.ignore` files in their ancestor directories.
+///
+/// Ancestors are searched up to the root of the git repository, and deeper files take precedence.
+/// `.git` directories are always ignored.
+#[derive(Debug)]
+struct IgnoreFilter {
+    cwd: PathBuf,
+    cache: HashMap<PathBuf, Vec<Gitignore>>,
+}
+
+impl IgnoreFilter {
+    const IGNORE_FILES: [&'static str; 2] = [".ignore", ".gitignore"];
+
+    fn new() -> Result<IgnoreFilter> {
+        let cwd = current_dir().context("getting current directory")?;
+        Ok(IgnoreFilter {
+            cwd,
+            cache: HashMap::new(),
+        })
+    }
+
+    fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        if path.components().any(|c| c.as_os_str() == ".git") {
+            return true;
+        }
+
+        let path = self.cwd.join(path);
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            for gitignore in self.ignores_in(d) {
+                match gitignore.matched_path_or_any_parents(&path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+
+            if d.join(".git").exists() {
+                break;
+            }
+            dir = d.parent();
+        }
+
+        false
+    }
+
+    fn ignores_in(&mut self, dir: &Path) -> &[Gitignore] {
+        self.cache.entry(dir.to_owned()).or_insert_with(|| {
+            let mut ignores = Vec::new();
+            for name in &IgnoreFilter::IGNORE_FILES {
+                let path = dir.join(name);
+                if !path.is_file() {
+                    continue;
+                }
+
+                let mut builder = GitignoreBuilder::new(dir);
+                if let Some(e) = builder.add(&path) {
+                    warn(format_args!("reading {}: {}", PathFmt(&path), e));
+                }
+                match builder.build() {
+                    Ok(gitignore) => ignores.push(gitignore),
+                    Err(e) => warn(format_args!("reading {}: {}", PathFmt(&path), e)),
+                }
+            }
+            ignores
+        })
+    }
+}
+
+/// Defaults read from `.fcrlf.toml`. Command line flags take precedence over them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProjectConfig {
+    target: Option<String>,
+    from: Option<String>,
+    encoding: Option<String>,
+    backup_suffix: Option<String>,
+    temp_suffix: Option<String>,
+    skip_if_contains: Option<String>,
+    replace_tabs: Option<String>,
+    jobs: Option<usize>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default)]
+    follow_symlinks: bool,
+    #[serde(default)]
+    no_ignore: bool,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    dereference_and_warn: bool,
+    #[serde(default)]
+    warn_mixed: bool,
+    #[serde(default)]
+    reject_mixed: bool,
+    #[serde(default)]
+    preserve_mtime: bool,
+    #[serde(default)]
+    inplace_rewrite: bool,
+    #[serde(default)]
+    check_unchanged: bool,
+    #[serde(default)]
+    backup: bool,
+    #[serde(default)]
+    binary: bool,
+    #[serde(default)]
+    strip_bom: bool,
+    #[serde(default)]
+    ensure_final_newline: bool,
+    #[serde(default)]
+    no_final_newline: bool,
+    #[serde(default)]
+    require_final_newline: bool,
+    #[serde(default)]
+    final_newline_only: bool,
+    #[serde(default)]
+    trim_trailing_whitespace: bool,
+    #[serde(default)]
+    all_tabs: bool,
+    #[serde(default)]
+    unicode_lineseps: bool,
+    #[serde(default)]
+    nel: bool,
+    #[serde(default)]
+    editorconfig: bool,
+    #[serde(default)]
+    regions: bool,
+    #[serde(default)]
+    treat_trailing_cr_as_text: bool,
+    #[serde(default)]
+    io_bound_jobs: bool,
+}
+
+impl ProjectConfig {
+    const FILE_NAME: &'static str = ".fcrlf.toml";
+
+    /// Finds the nearest config file in `dir` or its ancestors, and reads it.
+    fn find(dir: &Path) -> Result<Option<(PathBuf, ProjectConfig)>> {
+        for d in dir.ancestors() {
+            let path = d.join(ProjectConfig::FILE_NAME);
+            if !path.is_file() {
+                continue;
+            }
+
+            let raw = read(&path).with_context(|| format!("reading {}", PathFmt(&path)))?;
+            let config = ProjectConfig::parse(&raw)
+                .with_context(|| format!("parsing {}", PathFmt(&path)))?;
+            return Ok(Some((path, config)));
+        }
+        Ok(None)
+    }
+
+    fn parse(raw: &[u8]) -> Result<ProjectConfig> {
+        let config: ProjectConfig = toml::from_slice(raw)?;
+        if let Some(target) = &config.target {
+            target.parse::<Delim>().context("invalid 'target'")?;
+        }
+        if config.ensure_final_newline && config.no_final_newline {
+            return Err(Error::msg(
+                "'ensure_final_newline' and 'no_final_newline' can't be set together",
+            ));
+        }
+        if let Some(from) = &config.from {
+            from.parse::<Delim>().context("invalid 'from'")?;
+        }
+        if let Some(encoding) = &config.encoding {
+            if !ENCODINGS.contains(&encoding.as_str()) {
+                return Err(Error::msg(format!(
+                    "invalid 'encoding': {:?} (expected {})",
+                    encoding,
+                    ENCODINGS.join(", ")
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns whether the flag named `name` on the command line is set in the config.
+    fn flag(&self, name: &str) -> bool {
+        match name {
+            "recursive" => self.recursive,
+            "follow-symlinks" => self.follow_symlinks,
+            "no-ignore" => self.no_ignore,
+            "hidden" => self.hidden,
+            "dereference-and-warn" => self.dereference_and_warn,
+            "warn-mixed" => self.warn_mixed,
+            "reject-mixed" => self.reject_mixed,
+            "preserve-mtime" => self.preserve_mtime,
+            "inplace-rewrite" => self.inplace_rewrite,
+            "check-unchanged" => self.check_unchanged,
+            "backup" => self.backup,
+            "binary" => self.binary,
+            "strip-bom" => self.strip_bom,
+            "ensure-final-newline" => self.ensure_final_newline,
+            "no-final-newline" => self.no_final_newline,
+            "require-final-newline" => self.require_final_newline,
+            "final-newline-only" => self.final_newline_only,
+            "trim-trailing-whitespace" => self.trim_trailing_whitespace,
+            "all-tabs" => self.all_tabs,
+            "unicode-lineseps" => self.unicode_lineseps,
+            "nel" => self.nel,
+            "editorconfig" => self.editorconfig,
+            "regions" => self.regions,
+            "treat-trailing-cr-as-text" => self.treat_trailing_cr_as_text,
+            "io-bound-jobs" => self.io_bound_jobs,
+            _ => false,
+        }
+    }
+
+    /// Names of flags on the command line which can be set in the config.
+    const FLAGS: [&'static str; 25] = [
+        "recursive",
+        "follow-symlinks",
+        "no-ignore",
+        "hidden",
+        "dereference-and-warn",
+        "warn-mixed",
+        "reject-mixed",
+        "preserve-mtime",
+        "inplace-rewrite",
+        "check-unchanged",
+        "backup",
+        "binary",
+        "strip-bom",
+        "ensure-final-newline",
+        "no-final-newline",
+        "require-final-newline",
+        "final-newline-only",
+        "trim-trailing-whitespace",
+        "all-tabs",
+        "unicode-lineseps",
+        "nel",
+        "editorconfig",
+        "regions",
+        "treat-trailing-cr-as-text",
+        "io-bound-jobs",
+    ];
+
+    /// Names of options on the command line which can be set in the config, except `jobs`.
+    const VALUES: [&'static str; 7] = [
+        "to",
+        "from",
+        "encoding",
+        "backup-suffix",
+        "temp-suffix",
+        "skip-if-contains",
+        "replace-tabs",
+    ];
+
+    /// Checks settings merged with the command line `args` parsed into `matches` in the same way
+    /// as the command line, so that conflicting or missing options are reported as usage errors.
+    /// Settings overridden by the command line are not merged.
+    fn check_with(&self, args: &[OsString], matches: &ArgMatches<'_>) -> Result<()> {
+        let overridden = |name: &str| match name {
+            "to" => TARGET_ARGS.iter().any(|name| matches.is_present(name)),
+            _ => matches.occurrences_of(name) > 0,
+        };
+        let mut merged = Vec::new();
+        for &name in &ProjectConfig::FLAGS {
+            if self.flag(name) && !overridden(name) {
+                merged.push(OsString::from(format!("--{}", name)));
+            }
+        }
+        for &name in &ProjectConfig::VALUES {
+            match self.value(name) {
+                Some(value) if !overridden(name) => {
+                    merged.push(OsString::from(format!("--{}={}", name, value)));
+                }
+                _ => {}
+            }
+        }
+
+        // Placed before the patterns, which may follow `--`.
+        let mut args = args.to_vec();
+        args.splice(1.min(args.len())..1.min(args.len()), merged);
+        match app()
+            .setting(AppSettings::ColorNever)
+            .get_matches_from_safe(args)
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let message = e.message.lines().next().unwrap_or_default();
+                Err(Error::msg(
+                    message.trim_start_matches("error: ").to_string(),
+                ))
+            }
+        }
+    }
+
+    /// Returns the value of the option named `name` on the command line, if set in the config.
+    fn value(&self, name: &str) -> Option<&str> {
+        match name {
+            "to" => self.target.as_deref(),
+            "from" => self.from.as_deref(),
+            "encoding" => self.encoding.as_deref(),
+            "backup-suffix" => self.backup_suffix.as_deref(),
+            "temp-suffix" => self.temp_suffix.as_deref(),
+            "skip-if-contains" => self.skip_if_contains.as_deref(),
+            "replace-tabs" => self.replace_tabs.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Creates a new temporary file for `path`, named like `name.tmpN` where `.tmp` is `suffix`.
+/// It's created in `temp_dir` if given, or next to `path`, and removed when the returned path is dropped.
+fn create_tmp_file(
+    path: &Path,
+    temp_dir: Option<&Path>,
+    suffix: &str,
+) -> io::Result<(TempPath, File)> {
+    assert!(!path.is_dir(), "argument should be file: {:?}", path);
+
+    let file_name = path
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    for i in 0u64.. {
+        let file_name = format!("{}{}{}", file_name, suffix, i);
+        let res = match temp_dir {
+            Some(dir) => dir.join(file_name),
+            None => path.with_file_name(file_name),
+        };
+
+        // Files with the same name in different directories may share `temp_dir`.
+        match OpenOptions::new().write(true).create_new(true).open(&res) {
+            Ok(f) => return Ok((TempPath::new(res), f)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// Returns the name of the file which a temporary file named like `name.tmpN` was created for,
+/// where `.tmp` is `suffix`.
+fn tmp_file_base<'a>(tmp_name: &'a str, suffix: &str) -> Option<&'a str> {
+    let (base, n) = tmp_name.rsplit_once(suffix)?;
+    if base.is_empty() || n.is_empty() || !n.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(base)
+}
+
+/// Removes temporary files left for `inputs` by `create_tmp_file`, next to them or in `--temp-dir`.
+/// Returns the exit code, which is an error if any of them can't be removed.
+fn clean_tmp_files(inputs: &[Input], config: &Config) -> i32 {
+    // Names of inputs in each directory, which is listed only once.
+    let mut names: BTreeMap<PathBuf, HashSet<String>> = BTreeMap::new();
+    for input in inputs {
+        if let Input::File(p) = input {
+            let name = p
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let dir = p.parent().unwrap_or_else(|| Path::new(""));
+            if let Some(temp_dir) = &config.temp_dir {
+                names
+                    .entry(temp_dir.clone())
+                    .or_default()
+                    .insert(name.clone());
+            }
+            names.entry(dir.to_path_buf()).or_default().insert(name);
+        }
+    }
+
+    let (mut removed, mut failed) = (0, 0);
+    for (dir, names) in &names {
+        let list_dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let entries = match read_dir(list_dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn(format_args!(
+                    "can't read directory {}: {}",
+                    PathFmt(list_dir),
+                    e
+                ));
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let is_tmp = file_name
+                .to_str()
+                .and_then(|name| tmp_file_base(name, &config.temp_suffix))
+                .is_some_and(|base| names.contains(base));
+            if !is_tmp || !entry.file_type().is_ok_and(|t| t.is_file()) {
+                continue;
+            }
+
+            let path = dir.join(&file_name);
+            config.log(1, || format!("removing {}", PathFmt(&path)));
+            match remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(e) => {
+                    eprintln!("Error: removing {}: {}", PathFmt(&path), e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if !config.quiet {
+        eprintln!(
+            "removed {} temporary {}",
+            removed,
+            if removed == 1 { "file" } else { "files" }
+        );
+    }
+    if failed > 0 {
+        EXIT_ERROR
+    } else {
+        0
+    }
+}
+
+/// Displays delimiter types separated by commas, like `LF, CR, CRLF`, or `NO_DELIM` if empty.
+///
+/// They are always in the order of `Delim::ALL` followed by `LS`, `PS` and `NEL`,
+/// as scripts may parse the output.
+#[derive(Debug)]
+struct DelimSetFmt<'a>(&'a BTreeSet<Delim>);
+
+impl<'a> fmt::Display for DelimSetFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "NO_DELIM")?;
+        } else {
+            for (i, v) in self.0.iter().enumerate() {
+                if i == 0 {
+                    write!(f, "{}", v)?;
+                } else {
+                    write!(f, ", {}", v)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats bytes as hex, like `0A 0D`.
+#[derive(Debug)]
+struct HexFmt<'a>(&'a [u8]);
+
+impl<'a> fmt::Display for HexFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02X}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays a line with its delimiter as a visible marker, like `abc␍␊`.
+#[derive(Debug)]
+struct VisibleLine<'a>(&'a Line, Encoding);
+
+impl<'a> fmt::Display for VisibleLine<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self.1 {
+            Encoding::Utf8 => String::from_utf8_lossy(&self.0.text).to_string(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units: Vec<u16> = self
+                    .0
+                    .text
+                    .chunks_exact(2)
+                    .map(|u| match self.1 {
+                        Encoding::Utf16Be => u16::from_be_bytes([u[0], u[1]]),
+                        _ => u16::from_le_bytes([u[0], u[1]]),
+                    })
+                    .collect();
+                String::from_utf16_lossy(&units)
+                    .trim_start_matches('\u{FEFF}')
+                    .to_string()
+            }
+        };
+        let marker = match self.0.line_end {
+            Some(Delim::LF) => "␊",
+            Some(Delim::CR) => "␍",
+            Some(Delim::CRLF) => "␍␊",
+            Some(Delim::LS) => "<LS>",
+            Some(Delim::PS) => "<PS>",
+            Some(Delim::NEL) => "<NEL>",
+            None => "",
+        };
+        write!(f, "{}{}", text, marker)
+    }
+}
+
+#[derive(Debug)]
+struct DelimCountsFmt<'a>(&'a BTreeMap<Delim, usize>);
+
+impl<'a> fmt::Display for DelimCountsFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "NO_DELIM")?;
+        } else {
+            for (i, (d, n)) in self.0.iter().enumerate() {
+                if i == 0 {
+                    write!(f, "{}={}", d, n)?;
+                } else {
+                    write!(f, ", {}={}", d, n)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delim_set_fmt_order() {
+        let mut types = BTreeSet::new();
+        assert_eq!(DelimSetFmt(&types).to_string(), "NO_DELIM");
+        types.insert(Delim::CRLF);
+        types.insert(Delim::CR);
+        types.insert(Delim::LF);
+        assert_eq!(DelimSetFmt(&types).to_string(), "LF, CR, CRLF");
+        types.insert(Delim::PS);
+        types.insert(Delim::LS);
+        assert_eq!(DelimSetFmt(&types).to_string(), "LF, CR, CRLF, LS, PS");
+    }
+
+    #[test]
+    fn delim_counts_fmt() {
+        let mut counts = BTreeMap::new();
+        assert_eq!(DelimCountsFmt(&counts).to_string(), "NO_DELIM");
+        counts.insert(Delim::CRLF, 3);
+        counts.insert(Delim::LF, 120);
+        assert_eq!(DelimCountsFmt(&counts).to_string(), "LF=120, CRLF=3");
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert_eq!(parse_size("100B").unwrap(), 100);
+        assert_eq!(parse_size("500K").unwrap(), 500 * 1024);
+        assert_eq!(parse_size("50m").unwrap(), 50 * 1024 * 1024);
+        assert_eq!(parse_size("1G").unwrap(), 1 << 30);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("-1K").is_err());
+        assert!(parse_size("99999999999999G").is_err());
+    }
+
+    #[test]
+    fn hex_bytes() {
+        assert_eq!(parse_hex_bytes("0A 0D").unwrap(), b"\n\r");
+        assert_eq!(parse_hex_bytes("0a0d1e").unwrap(), b"\n\r\x1e");
+        assert!(parse_hex_bytes("").is_err());
+        assert!(parse_hex_bytes("0A 0").is_err());
+        assert!(parse_hex_bytes("0G").is_err());
+        assert!(parse_hex_bytes("+A").is_err());
+        assert_eq!(HexFmt(b"\n\r").to_string(), "0A 0D");
+    }
+
+    #[test]
+    fn file_list() {
+        let list = parse_file_list(b"a.txt\nsrc/b c.rs\r\n\nd\n", false).unwrap();
+        let expected: Vec<PathBuf> = vec!["a.txt".into(), "src/b c.rs".into(), "d".into()];
+        assert_eq!(list, expected);
+
+        let list = parse_file_list(b"a\nb\0c\0", true).unwrap();
+        let expected: Vec<PathBuf> = vec!["a\nb".into(), "c".into()];
+        assert_eq!(list, expected);
+
+        assert!(parse_file_list(b"", false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn path_filter() {
+        let filter = PathFilter::new(vec!["*.rs", "*.md"], vec!["*.min.*"]).unwrap();
+        assert!(filter.is_match(Path::new("src/main.rs")));
+        assert!(filter.is_match(Path::new("README.md")));
+        assert!(!filter.is_match(Path::new("src/main.js")));
+        assert!(!filter.is_match(Path::new("dist/a.min.rs")));
+
+        let filter = PathFilter::new(vec![], vec!["target/**", "src/*.js"]).unwrap();
+        assert!(filter.is_match(Path::new("src/main.rs")));
+        assert!(filter.is_match(Path::new("src/sub/a.js")));
+        assert!(!filter.is_match(Path::new("src/a.js")));
+        assert!(!filter.is_match(Path::new("./target/debug/out.txt")));
+
+        assert!(PathFilter::new(vec!["[a"], vec![]).is_err());
+    }
+
+    #[test]
+    fn progress_bar() {
+        let progress = Progress::new(4);
+        assert_eq!(
+            progress.bar(1),
+            format!("[{}{}] 1/4 files", "#".repeat(7), " ".repeat(23))
+        );
+        assert_eq!(progress.bar(4), format!("[{}] 4/4 files", "#".repeat(30)));
+        assert_eq!(
+            Progress::new(0).bar(0),
+            format!("[{}] 0/0 files", "#".repeat(30))
+        );
+    }
+
+    #[test]
+    fn paint() {
+        assert_eq!(Paint::new(false, ANSI_RED, "a").to_string(), "a");
+        assert_eq!(
+            Paint::new(true, ANSI_RED, "a").to_string(),
+            "\x1b[31ma\x1b[0m"
+        );
+        assert!(ColorChoice::Auto.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+    }
+
+    #[test]
+    fn diff() {
+        let before = FileContents::from_bytes(b"a\r\nb\nc\r\nd");
+        let after = FileContents::from_bytes(b"a\nb\nc\nd\n");
+        assert_eq!(
+            diff_lines(&"x.txt", &before, &after),
+            "--- x.txt\n+++ x.txt\n\
+             @@ -1,1 +1,1 @@\n-a␍␊\n+a␊\n\
+             @@ -3,2 +3,2 @@\n-c␍␊\n-d\n+c␊\n+d␊\n"
+        );
+        assert_eq!(
+            diff_lines(&"x.txt", &before, &before),
+            "--- x.txt\n+++ x.txt\n"
+        );
+    }
+
+    #[test]
+    fn gzip_level_from_header() {
+        for &level in &[
+            Compression::best(),
+            Compression::fast(),
+            Compression::default(),
+        ] {
+            let mut w = GzEncoder::new(Vec::new(), level);
+            w.write_all(b"abc\n").unwrap();
+            let raw = w.finish().unwrap();
+            assert_eq!(gzip_level(&raw), level);
+        }
+        assert_eq!(gzip_level(b""), Compression::default());
+    }
+
+    #[test]
+    fn backup_path() {
+        let backup = Backup {
+            suffix: ".bak".to_string(),
+            overwrite: false,
+        };
+        assert_eq!(
+            backup.path(Path::new("dir/a.txt")),
+            PathBuf::from("dir/a.txt.bak")
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let lf: BTreeSet<Delim> = [Delim::LF].iter().copied().collect();
+        let mixed: BTreeSet<Delim> = [Delim::LF, Delim::CRLF].iter().copied().collect();
+        let outcomes = vec![
+            Outcome::Correct(lf.clone()),
+            Outcome::Correct(lf),
+            Outcome::NeedsConversion(mixed, [Delim::CRLF].iter().copied().collect()),
+            Outcome::Binary,
+            Outcome::Failed("error".to_string()),
+        ];
+        let summary = Summary::new(&outcomes);
+
+        assert_eq!(
+            summary.message(true),
+            "converted 1 file, 2 already correct, 1 skipped (binary)"
+        );
+        assert_eq!(
+            summary.message(false),
+            "1 file needs conversion, 2 already correct, 1 skipped (binary)"
+        );
+        assert_eq!(summary.stats(), "LF: 3 files, CR: 0 files, CRLF: 1 file");
+        assert_eq!(
+            summary.delimiter_tally(),
+            "LF only: 2 files, CR only: 0 files, CRLF only: 0 files, mixed: 1 file, no delimiter: 0 files"
+        );
+
+        let lf: BTreeSet<Delim> = [Delim::LF].iter().copied().collect();
+        let summary = Summary::new(&[
+            Outcome::Correct(lf.clone()),
+            Outcome::MissingFinalNewline(lf),
+        ]);
+        assert_eq!(
+            summary.message(false),
+            "0 files need conversion, 1 already correct, 1 without final newline, 0 skipped (binary)"
+        );
+    }
+
+    #[test]
+    fn json_entry() {
+        let input = Input::File(PathBuf::from("dir/a.txt"));
+        let outcome = Outcome::NeedsConversion(
+            [Delim::LF, Delim::CRLF].iter().copied().collect(),
+            [Delim::CRLF].iter().copied().collect(),
+        );
+        let entry = JsonEntry::new(&input, &outcome, "-").unwrap();
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"path":"dir/a.txt","delimiters":["LF","CRLF"],"needs_conversion":true}"#
+        );
+
+        assert!(JsonEntry::new(&input, &Outcome::Binary, "-").is_none());
+
+        let outcome = Outcome::Correct([Delim::LF].iter().copied().collect());
+        let entry = JsonEntry::new(&Input::Stdin, &outcome, "src/lib.rs").unwrap();
+        assert_eq!(entry.path, "src/lib.rs");
+    }
+
+    #[test]
+    fn project_config() {
+        let raw = b"target = \"lf\"\nexclude = [\"*.bin\"]\nensure_final_newline = true\n";
+        let config = ProjectConfig::parse(raw).unwrap();
+        assert_eq!(config.value("to"), Some("lf"));
+        assert_eq!(config.value("from"), None);
+        assert_eq!(config.exclude, vec!["*.bin".to_string()]);
+        assert!(config.ensure_final_newline);
+        assert!(!config.flag("recursive"));
+
+        assert!(ProjectConfig::parse(b"target = \"lf\"\nrecursive = true")
+            .unwrap()
+            .flag("recursive"));
+        assert!(ProjectConfig::parse(b"").is_ok());
+        assert!(ProjectConfig::parse(b"target = \"utf-8\"").is_err());
+        assert!(ProjectConfig::parse(b"encoding = \"latin1\"").is_err());
+        assert!(ProjectConfig::parse(b"unknown_key = true").is_err());
+        assert!(
+            ProjectConfig::parse(b"ensure_final_newline = true\nno_final_newline = true").is_err()
+        );
+
+        let root = temp_dir("config");
+        let sub = root.join("a").join("b");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(root.join(ProjectConfig::FILE_NAME), "target = \"crlf\"").unwrap();
+        let (path, config) = ProjectConfig::find(&sub).unwrap().unwrap();
+        assert_eq!(path, root.join(ProjectConfig::FILE_NAME));
+        assert_eq!(config.value("to"), Some("crlf"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn correct_file_untouched() {
+        let dir = temp_dir("untouched");
+        let path = dir.join("a.txt");
+        std::fs::write(&path, "a\nb\n").unwrap();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let outcome = convert_file(&path, &config_to(Delim::LF)).unwrap();
+        assert!(matches!(outcome, Outcome::Correct(_)));
+        assert_eq!(metadata(&path).unwrap().modified().unwrap(), old);
+        assert_eq!(read_dir(&dir).unwrap().count(), 1);
+
+        let outcome = convert_file(&path, &config_to(Delim::CRLF)).unwrap();
+        assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+        assert_ne!(metadata(&path).unwrap().modified().unwrap(), old);
+        assert_eq!(read(&path).unwrap(), b"a\r\nb\r\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tiny_files_untouched() {
+        let dir = temp_dir("tiny");
+        let mut config = config_to(Delim::LF);
+        config.options.final_newline = FinalNewline::Ensure;
+        for raw in &[&b""[..], b"\n", b"\xEF\xBB\xBF"] {
+            let path = dir.join("a.txt");
+            std::fs::write(&path, raw).unwrap();
+            let outcome = convert_file(&path, &config).unwrap();
+            assert!(matches!(outcome, Outcome::Correct(_)));
+            assert_eq!(read(&path).unwrap(), *raw);
+            assert_eq!(read_dir(&dir).unwrap().count(), 1);
+        }
+
+        let path = dir.join("a.txt");
+        for &(raw, expected) in &[(&b"\r\n"[..], &b"\n"[..]), (b"\r", b"\n"), (b"a", b"a\n")] {
+            std::fs::write(&path, raw).unwrap();
+            let outcome = convert_file(&path, &config).unwrap();
+            assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+            assert_eq!(read(&path).unwrap(), expected);
+            assert_eq!(read_dir(&dir).unwrap().count(), 1);
+        }
+
+        config.options.strip_bom = true;
+        std::fs::write(&path, b"\xEF\xBB\xBF").unwrap();
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+        assert_eq!(read(&path).unwrap(), b"");
+        assert_eq!(read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn final_newline_only() {
+        let dir = temp_dir("final-only");
+        let path = dir.join("a.txt");
+        let mut config = config_to(Delim::LF);
+        config.options.final_newline_only = true;
+        config.verify = true;
+
+        std::fs::write(&path, b"a\r\nb\rc\r\n\r\n").unwrap();
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+        assert_eq!(read(&path).unwrap(), b"a\r\nb\rc\n");
 
-    let do_covert = !matches.is_present("detect");
-    let target_delim = match () {
-        () if matches.is_present("crlf") => Delim::CRLF,
-        () if matches.is_present("lf") => Delim::LF,
-        () if matches.is_present("cr") => Delim::CR,
-        _ => {
-            return Err(Error::msg(
-                "No target delimiter is specified, use '--lf', '--crlf' or '--cr'.",
-            ))
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::Correct(_)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_removes_temp_file() {
+        let dir = temp_dir("failed-write");
+        let path = dir.join("a.txt");
+        std::fs::write(&path, "a\r\n").unwrap();
+
+        let stamp = FileStamp::new(&metadata(&path).unwrap());
+        let res = write_file(&path, &config_to(Delim::LF), stamp, None, |w| {
+            w.write_all(b"a")?;
+            Err(Error::msg("broken"))
+        });
+        assert!(res.is_err());
+        assert_eq!(read(&path).unwrap(), b"a\r\n");
+        assert_eq!(read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn out_paths() {
+        let cwd = current_dir().unwrap();
+        let out = Path::new("/out");
+        assert_eq!(
+            out_path(out, Path::new("./src/a.txt")).unwrap(),
+            Path::new("/out/src/a.txt")
+        );
+        assert_eq!(
+            out_path(out, &cwd.join("src/../b.txt")).unwrap(),
+            Path::new("/out/b.txt")
+        );
+        assert!(out_path(out, Path::new("../a.txt")).is_err());
+    }
+
+    #[test]
+    fn check_unchanged() {
+        let dir = temp_dir("check-unchanged");
+        let path = dir.join("a.txt");
+        std::fs::write(&path, "a\r\n").unwrap();
+        let config = Config {
+            check_unchanged: true,
+            ..config_to(Delim::LF)
+        };
+
+        let stamp = FileStamp::new(&metadata(&path).unwrap());
+        let res = write_file(&path, &config, stamp, None, |w| {
+            // Another process appends to the file meanwhile.
+            std::fs::write(&path, "a\r\nb\r\n")?;
+            w.write_all(b"a\n")?;
+            Ok(())
+        });
+        let message = format!("{:#}", res.unwrap_err());
+        assert!(
+            message.contains("was changed while converting"),
+            "{}",
+            message
+        );
+        assert_eq!(read(&path).unwrap(), b"a\r\nb\r\n");
+        assert_eq!(read_dir(&dir).unwrap().count(), 1);
+
+        convert_file(&path, &config).unwrap();
+        assert_eq!(read(&path).unwrap(), b"a\nb\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn out_dir() {
+        // Paths under `--out-dir` are relative to the current directory, which is the package root.
+        let dir = Path::new("target").join(format!("fcrlf-out-dir-{}", std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
         }
-    };
-    let mut target_delim_set = BTreeSet::new();
-    target_delim_set.insert(target_delim);
-    let target_delim_set = target_delim_set;
+        let src = dir.join("src");
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("a.txt"), "a\r\n").unwrap();
+        std::fs::write(src.join("sub/b.txt"), "b\n").unwrap();
+        let out = dir.join("out");
+        let config = Config {
+            out_dir: Some(out.clone()),
+            check_unchanged: true,
+            ..config_to(Delim::LF)
+        };
 
-    let patterns = matches.values_of("patterns").expect("files should exists");
+        for name in &["a.txt", "sub/b.txt"] {
+            convert_file(&src.join(name), &config).unwrap();
+        }
+        assert_eq!(read(out.join(&src).join("a.txt")).unwrap(), b"a\n");
+        assert_eq!(read(out.join(&src).join("sub/b.txt")).unwrap(), b"b\n");
+        assert_eq!(read(src.join("a.txt")).unwrap(), b"a\r\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
-    for pat in patterns {
-        let pathes = glob(pat).with_context(|| format!("listing files for pattern: {:?}", pat))?;
-        for p in pathes {
-            let p = p.with_context(|| format!("reading path in {:?}", pat))?;
-            if !p.exists() || !p.is_file() {
-                continue;
-            }
+    #[test]
+    fn forbidden_delims() {
+        let forbidden: BTreeSet<Delim> = [Delim::CR, Delim::CRLF].iter().copied().collect();
+        let mut config = config_to(Delim::LF);
+        config.target_delim_set = kept_delims(None, &forbidden);
+        config.options.forbidden = forbidden;
+        for (raw, expected) in &[(&b"a\nb\xE2\x80\xA8"[..], false), (b"a\nb\r", true)] {
+            let scan = FileContents::parse(
+                raw,
+                Encoding::Utf8,
+                &SplitOptions {
+                    unicode_lineseps: true,
+                    ..SplitOptions::default()
+                },
+            )
+            .scan();
+            assert_eq!(config.needs_conversion(&scan), *expected);
+        }
+        assert!(kept_delims(None, &BTreeSet::new()).is_empty());
+    }
 
-            let file_contents_raw =
-                read(&p).with_context(|| format!("reading file contents of {}", PathFmt(&p)))?;
-
-            let file_contents = FileContents::from_bytes(&file_contents_raw);
-
-            let delim_types = file_contents.delim_types();
-            if !delim_types.is_subset(&target_delim_set) {
-                if do_covert {
-                    let tmp_path = tmp_path(&p);
-                    let f = File::create(&tmp_path).with_context(|| {
-                        format!("creating tmporary file: {}", PathFmt(&tmp_path))
-                    })?;
-                    let mut f = BufWriter::with_capacity(BUF_SIZE, f);
-
-                    file_contents
-                        .write_to(&mut f, target_delim)
-                        .with_context(|| {
-                            format!(
-                                "writing file contents to tmporary file: {}",
-                                PathFmt(&tmp_path)
-                            )
-                        })?;
-
-                    f.flush().with_context(|| {
-                        format!(
-                            "writing file contents to tmporary file: {}",
-                            PathFmt(&tmp_path)
-                        )
-                    })?;
-                    drop(f);
-
-                    rename(&tmp_path, &p).with_context(|| {
-                        format!(
-                            "renaming temporary file: {} => {}",
-                            PathFmt(&tmp_path),
-                            PathFmt(&p)
-                        )
-                    })?;
-                } else {
-                    println!("{}: {}", PathFmt(&p), DelimSetFmt(&delim_types));
-                }
-            }
+    #[test]
+    fn relative() {
+        let base = Path::new("/repo/src");
+        assert_eq!(
+            relative_to(Path::new("/repo/src/a.rs"), base),
+            PathBuf::from("a.rs")
+        );
+        assert_eq!(
+            relative_to(Path::new("/repo/README.md"), base),
+            PathBuf::from("../README.md")
+        );
+        assert_eq!(
+            relative_to(Path::new("/repo/docs/a.md"), base),
+            PathBuf::from("../docs/a.md")
+        );
+
+        let cwd = Path::new("/repo/docs");
+        assert_eq!(
+            display_relative(Path::new("./../src/./a.rs"), base, cwd),
+            PathBuf::from("a.rs")
+        );
+        assert_eq!(
+            display_relative(Path::new("/repo/src"), base, cwd),
+            PathBuf::from(".")
+        );
+    }
+
+    #[test]
+    fn histogram() {
+        let config = Config {
+            histogram: Some(Arc::new(Mutex::new(BTreeMap::new()))),
+            ..config_to(Delim::LF)
+        };
+        let dir = temp_dir("histogram");
+        for (name, raw) in &[("a.txt", "a\nb\nc\r\n"), ("b.txt", "a\nb\n")] {
+            let path = dir.join(name);
+            std::fs::write(&path, raw).unwrap();
+            convert_file(
+                &path,
+                &Config {
+                    do_covert: false,
+                    full_scan: true,
+                    ..config.clone()
+                },
+            )
+            .unwrap();
         }
+        let counts = config.histogram.as_ref().unwrap().lock().unwrap();
+        assert_eq!(histogram_table(&counts), "LF    4\nCR    0\nCRLF  1\n");
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    Ok(())
-}
+    #[test]
+    fn match_first_target() {
+        let config = config_to(Delim::LF);
+        let dir = temp_dir("match-first");
+        for (raw, expected) in &[
+            ("a\r\nb\r\nc\n", Delim::CRLF),
+            ("a\rb\nc\r\n", Delim::LF),
+            ("a", Delim::LF),
+            ("a\rb\rc\n", Delim::CR),
+        ] {
+            let path = dir.join("first.txt");
+            std::fs::write(&path, raw).unwrap();
+            assert_eq!(
+                first_file_target(&path, &config).unwrap(),
+                *expected,
+                "{:?}",
+                raw
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
-fn tmp_path(path: &Path) -> PathBuf {
-    assert!(path.is_file(), "argument should be file: {:?}", path);
+    #[test]
+    fn mmap_scan() {
+        let config = Config {
+            do_covert: false,
+            mmap: true,
+            full_scan: true,
+            ..config_to(Delim::LF)
+        };
+        let dir = temp_dir("mmap");
+        let path = dir.join("a.txt");
+        for (raw, expected) in &[
+            (&b"a\r\nb\n"[..], &[Delim::LF, Delim::CRLF][..]),
+            (b"", &[]),
+            (b"\xFF\xFEa\0\r\0", &[Delim::CR]),
+        ] {
+            std::fs::write(&path, raw).unwrap();
+            let types = match convert_file(&path, &config).unwrap() {
+                Outcome::NeedsConversion(types, _) | Outcome::Correct(types) => types,
+                outcome => panic!("unexpected {:?}", outcome),
+            };
+            assert_eq!(types.into_iter().collect::<Vec<_>>(), *expected);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
-    let file_name = path
-        .file_name()
-        .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or(String::new());
+    #[test]
+    fn offenders() {
+        let mut config = config_to(Delim::LF);
+        let scan = FileContents::from_bytes(b"a\nb\r\nc\nd\re\n").scan();
+        assert_eq!(config.offenders(&scan), 2);
+        config.target_delim_set = kept_delims(Some(Delim::CR), &BTreeSet::new());
+        assert_eq!(config.offenders(&scan), 1);
+        let scan = FileContents::from_bytes(b"a\n").scan();
+        assert_eq!(config.offenders(&scan), 0);
+    }
 
-    for i in 0u64.. {
-        let file_name = format!("{}.tmp{}", file_name, i);
-        let res = path.with_file_name(file_name);
+    #[test]
+    fn print0_records() {
+        let scan = FileContents::from_bytes(b"a\r\nb").scan();
+        let name = "dir/a file.txt";
+        let config = Config {
+            do_covert: false,
+            require_final_newline: true,
+            ..config_to(Delim::LF)
+        };
+        assert_eq!(
+            config.detected_record(&name, &scan, None, true, false),
+            "dir/a file.txt: CRLF (no final newline)\n"
+        );
+        let config = Config {
+            print0: true,
+            ..config
+        };
+        assert_eq!(
+            config.detected_record(&name, &scan, None, true, false),
+            "dir/a file.txt\0"
+        );
+    }
+
+    #[test]
+    fn convert_zip_entries() {
+        use std::io::Cursor;
+        use zip::write::SimpleFileOptions;
+        use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+        let dir = temp_dir("zip");
+        let path = dir.join("a.zip");
+        let time = DateTime::from_date_and_time(2020, 1, 2, 3, 4, 6).unwrap();
+        let options = SimpleFileOptions::default()
+            .last_modified_time(time)
+            .unix_permissions(0o755);
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("run.sh", options).unwrap();
+        zip.write_all(b"echo a\r\necho b\r\n").unwrap();
+        zip.start_file(
+            "data.bin",
+            options.compression_method(CompressionMethod::Stored),
+        )
+        .unwrap();
+        zip.write_all(b"\0\r\n").unwrap();
+        zip.start_file("notes.md", options).unwrap();
+        zip.write_all(b"a\r\n").unwrap();
+        zip.add_directory("docs/", options).unwrap();
+        zip.finish().unwrap();
+
+        let mut config = config_to(Delim::LF);
+        config.zip = Some(Arc::new(
+            PathFilter::new(vec!["*.sh", "*.bin"], None).unwrap(),
+        ));
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::Correct(_)));
+        // Converted archives are not read again, so it's rejected rather than ignored.
+        assert!(app()
+            .get_matches_from_safe(["fcrlf", "--zip", "--verify", "--to", "lf", "a.zip"])
+            .is_err());
+
+        let raw = read(&path).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(&raw[..])).unwrap();
+        assert_eq!(archive.len(), 4);
+        let mut read_entry = |name: &str| {
+            let mut entry = archive.by_name(name).unwrap();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            (contents, entry.unix_mode(), entry.last_modified())
+        };
+        let (contents, mode, modified) = read_entry("run.sh");
+        assert_eq!(contents, b"echo a\necho b\n");
+        assert_eq!(mode.map(|m| m & 0o777), Some(0o755));
+        assert_eq!(modified, Some(time));
+        assert_eq!(read_entry("data.bin").0, b"\0\r\n");
+        assert_eq!(read_entry("notes.md").0, b"a\r\n");
+        assert_eq!(read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ignore_filter() {
+        let dir = temp_dir("ignore");
+        // Ignore files above the repository are not read.
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        std::fs::write(dir.join(".gitignore"), "*.log\n!keep.log\nbuild/\n").unwrap();
+        std::fs::write(dir.join("sub/.gitignore"), "*.tmp\n!b.log\n").unwrap();
+
+        let mut filter = IgnoreFilter::new().unwrap();
+        for &(name, is_dir, ignored) in &[
+            ("a.txt", false, false),
+            ("a.log", false, true),
+            ("keep.log", false, false),
+            ("a.tmp", false, false),
+            ("build", true, true),
+            ("build/a.txt", false, true),
+            ("sub/a.tmp", false, true),
+            ("sub/a.log", false, true),
+            ("sub/b.log", false, false),
+            ("sub/a.txt", false, false),
+            (".git/config", false, true),
+        ] {
+            assert_eq!(
+                filter.is_ignored(&dir.join(name), is_dir),
+                ignored,
+                "{}",
+                name
+            );
+        }
 
-        if !res.exists() {
-            return res;
+        for name in &["a.log", "keep.log", "sub/a.log", "sub/b.log"] {
+            std::fs::write(dir.join(name), "a\n").unwrap();
         }
+        let matched = |pat: &Path| {
+            let mut filter = IgnoreFilter::new().unwrap();
+            glob_paths(pat.to_str().unwrap(), Some(&mut filter), false).unwrap()
+        };
+        assert_eq!(matched(&dir.join("*.log")), vec![dir.join("keep.log")]);
+        assert_eq!(matched(&dir.join("sub/*.log")), vec![dir.join("sub/b.log")]);
+        // Explicitly named paths are not filtered.
+        assert_eq!(matched(&dir.join("a.log")), vec![dir.join("a.log")]);
+        assert_eq!(matched(&dir.join("sub/a.log")), vec![dir.join("sub/a.log")]);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-    unreachable!()
-}
 
-#[derive(Debug, PartialEq)]
-struct FileContents {
-    lines: Vec<Line>,
-}
-
-impl FileContents {
-    fn from_bytes(mut bytes: &[u8]) -> FileContents {
-        let mut lines = Vec::new();
-        let mut cur_line = Line::new();
-
-        while !bytes.is_empty() {
-            if bytes.starts_with(b"\r\n") {
-                cur_line.line_end = Some(Delim::CRLF);
-                bytes = &bytes[2..];
-                lines.push(cur_line);
-                cur_line = Line::new();
-            } else if bytes[0] == b'\n' {
-                cur_line.line_end = Some(Delim::LF);
-                bytes = &bytes[1..];
-                lines.push(cur_line);
-                cur_line = Line::new();
-            } else if bytes[0] == b'\r' {
-                cur_line.line_end = Some(Delim::CR);
-                bytes = &bytes[1..];
-                lines.push(cur_line);
-                cur_line = Line::new();
-            } else {
-                cur_line.text.push(bytes[0]);
-                bytes = &bytes[1..];
-            }
+    #[test]
+    fn walk_hidden() {
+        let dir = temp_dir("hidden");
+        std::fs::create_dir_all(dir.join(".config")).unwrap();
+        for name in &[".gitattributes", ".config/a.txt", "b.txt"] {
+            std::fs::write(dir.join(name), "a\n").unwrap();
         }
+        let walked = |hidden| {
+            let mut files = Vec::new();
+            walk_dir(&dir, &mut files, None, hidden, false);
+            files
+        };
+        assert_eq!(walked(false), vec![dir.join("b.txt")]);
+        assert_eq!(
+            walked(true),
+            vec![
+                dir.join(".config/a.txt"),
+                dir.join(".gitattributes"),
+                dir.join("b.txt")
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_broken_symlink() {
+        let dir = temp_dir("broken");
+        std::fs::write(dir.join("a.txt"), "a\n").unwrap();
+        std::os::unix::fs::symlink(dir.join("a.txt"), dir.join("b.txt")).unwrap();
+        std::os::unix::fs::symlink(dir.join("gone.txt"), dir.join("c.txt")).unwrap();
+        assert!(!is_broken_symlink(&dir.join("a.txt")));
+        assert!(!is_broken_symlink(&dir.join("b.txt")));
+        assert!(is_broken_symlink(&dir.join("c.txt")));
+        assert!(!is_broken_symlink(&dir.join("gone.txt")));
 
-        lines.push(cur_line);
-        FileContents { lines }
+        for &warn_broken in &[false, true] {
+            let mut files = Vec::new();
+            walk_dir(&dir, &mut files, None, false, warn_broken);
+            assert_eq!(files, vec![dir.join("a.txt"), dir.join("b.txt")]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    fn delim_types(&self) -> BTreeSet<Delim> {
-        let mut types = BTreeSet::new();
+    #[test]
+    fn project_config_conflicts() {
+        let check = |raw: &[u8], args: &[&str]| {
+            let args: Vec<OsString> = ["fcrlf", "a.txt"]
+                .iter()
+                .chain(args)
+                .map(OsString::from)
+                .collect();
+            let matches = app().get_matches_from_safe(&args).unwrap();
+            ProjectConfig::parse(raw)
+                .unwrap()
+                .check_with(&args, &matches)
+        };
+        assert!(check(b"check_unchanged = true", &["--out-dir", "out"]).is_ok());
+        assert!(check(b"inplace_rewrite = true", &["--out-dir", "out"]).is_err());
+        assert!(check(b"final_newline_only = true", &["--ensure-final-newline"]).is_err());
+        assert!(check(b"final_newline_only = true\nregions = true", &[]).is_err());
+        assert!(check(b"all_tabs = true", &[]).is_err());
+        assert!(check(b"all_tabs = true", &["--replace-tabs", "4"]).is_ok());
+        assert!(check(b"all_tabs = true\nreplace_tabs = \"4\"", &[]).is_ok());
+        assert!(check(b"from = \"cr\"", &["--auto-to"]).is_err());
+        assert!(check(b"from = \"cr\"", &["--from", "lf", "--to", "crlf"]).is_ok());
+        // Overridden by the command line.
+        assert!(check(b"target = \"crlf\"", &["--lf"]).is_ok());
+        assert!(check(b"target = \"crlf\"", &["--auto-to"]).is_ok());
+        assert!(check(b"io_bound_jobs = true", &["-j", "2"]).is_ok());
+        assert!(check(b"recursive = true", &["--", "-a.txt"]).is_ok());
+
+        let message = format!(
+            "{}",
+            check(b"inplace_rewrite = true", &["--out-dir", "out"]).unwrap_err()
+        );
+        assert!(message.contains("--inplace-rewrite"), "{}", message);
+    }
+
+    #[test]
+    fn job_counts() {
+        let jobs = |args: &[&str], raw: &[u8]| {
+            let matches = app()
+                .get_matches_from_safe(["fcrlf", "a.txt"].iter().chain(args))
+                .unwrap();
+            let project = ProjectConfig::parse(raw).unwrap();
+            let jobs = job_count(&matches, &project).unwrap();
+            (jobs, io_bound_jobs(&matches, &project))
+        };
+        assert_eq!(jobs(&[], b""), (0, None));
+        assert_eq!(jobs(&["-j", "3"], b""), (3, None));
+        assert_eq!(jobs(&[], b"jobs = 2"), (2, None));
+        assert_eq!(jobs(&["-j", "3"], b"jobs = 2"), (3, None));
+
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let readers = Some(cpus * IO_BOUND_JOBS_PER_CPU);
+        assert_eq!(jobs(&["--io-bound-jobs"], b""), (0, readers));
+        assert_eq!(jobs(&[], b"io_bound_jobs = true"), (0, readers));
+        assert_eq!(jobs(&["-j", "3", "--io-bound-jobs"], b""), (3, readers));
+        assert_eq!(jobs(&[], b"jobs = 2\nio_bound_jobs = true"), (2, readers));
+    }
+
+    #[test]
+    fn read_ahead() {
+        let dir = temp_dir("read-ahead");
+        let inputs: Vec<Input> = (0..20)
+            .map(|i| {
+                let path = dir.join(format!("{}.txt", i));
+                std::fs::write(&path, if i % 2 == 0 { "a\r\n" } else { "a\n" }).unwrap();
+                Input::File(path)
+            })
+            .collect();
+        let config = config_to(Delim::LF);
+        let convert = |i: usize, read_ahead: Option<ReadAhead>| match &inputs[i] {
+            Input::File(p) => convert_read_ahead(p, &config, read_ahead.unwrap()),
+            Input::Stdin => unreachable!(),
+        };
 
-        for l in &self.lines {
-            if let Some(d) = l.line_end {
-                types.insert(d);
+        let outcomes = process_read_ahead(&inputs, &config, 3, convert).unwrap();
+        assert_eq!(outcomes.len(), inputs.len());
+        for (i, (input, outcome)) in inputs.iter().zip(&outcomes).enumerate() {
+            let converted = matches!(outcome, Outcome::NeedsConversion(..));
+            assert_eq!(converted, i % 2 == 0, "{}", i);
+            if let Input::File(p) = input {
+                assert_eq!(read(p).unwrap(), b"a\n");
             }
         }
 
-        types
+        // Readers stop once processing fails, instead of waiting for it forever.
+        let failed = process_read_ahead(&inputs, &config, 1, |_, _| Err(Error::msg("failed")));
+        assert!(failed.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn completions() {
+        let mut out = Vec::new();
+        app().gen_completions_to("fcrlf", Shell::Bash, &mut out);
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains("--match-first"));
+        assert!(script.contains("--generate-completions"));
     }
 
-    fn write_to(&self, w: &mut impl Write, delim: Delim) -> Result<()> {
-        for l in &self.lines {
-            l.write_to(w, delim)?;
+    #[test]
+    fn auto_to_flags_mixed() {
+        let config = Config {
+            auto_to: true,
+            ..config_to(Delim::LF)
+        };
+        for (raw, expected) in &[
+            (&b"a\r\nb\r\n"[..], false),
+            (b"a\rb\r", false),
+            (b"a", false),
+            (b"a\r\nb\r\nc\n", true),
+        ] {
+            let scan = FileContents::from_bytes(raw).scan();
+            assert_eq!(config.needs_conversion(&scan), *expected, "{:?}", raw);
         }
-        Ok(())
+        let scan = FileContents::from_bytes(b"a\r\nb\r\nc\n").scan();
+        assert_eq!(config.target_for(&scan), Delim::CRLF);
     }
-}
 
-#[derive(Debug, Clone, PartialEq)]
-struct Line {
-    text: Vec<u8>,
-    line_end: Option<Delim>,
-}
+    #[test]
+    fn check_consistency() {
+        let matches = app()
+            .get_matches_from_safe(["fcrlf", "--check", "--check-consistency", "a.txt"])
+            .unwrap();
+        assert!(matches.is_present("check-consistency"));
+        assert!(app()
+            .get_matches_from_safe(["fcrlf", "--check-consistency", "--to", "lf", "a.txt"])
+            .is_err());
+
+        let dir = temp_dir("consistency");
+        let path = dir.join("a.txt");
+        let convert = Config {
+            auto_to: true,
+            ..config_to(Delim::LF)
+        };
+        let detect = Config {
+            do_covert: false,
+            ..convert.clone()
+        };
+        for &(raw, converted) in &[
+            (&b"a\r\nb\r\n"[..], None),
+            (b"a\rb\r", None),
+            (b"a\r\nb\r\nc\n", Some(&b"a\r\nb\r\nc\r\n"[..])),
+            (b"a\nb\rc\n", Some(b"a\nb\nc\n")),
+        ] {
+            std::fs::write(&path, raw).unwrap();
+            let outcome = convert_file(&path, &detect).unwrap();
+            assert_eq!(
+                matches!(outcome, Outcome::NeedsConversion(..)),
+                converted.is_some(),
+                "{:?}",
+                raw
+            );
+            assert_eq!(read(&path).unwrap(), raw);
 
-impl Line {
-    fn new() -> Line {
-        Line {
-            text: Vec::new(),
-            line_end: None,
+            convert_file(&path, &convert).unwrap();
+            assert_eq!(read(&path).unwrap(), converted.unwrap_or(raw), "{:?}", raw);
         }
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    fn write_to(&self, w: &mut impl Write, delim: Delim) -> Result<()> {
-        w.write_all(&self.text)?;
-        if let Some(_) = self.line_end {
-            delim.write_to(w)?;
+    #[test]
+    fn accept_single_delims() {
+        let mut config = config_to(Delim::LF);
+        config.accepted = [Delim::LF, Delim::CRLF].iter().copied().collect();
+        config.options.trim_trailing_whitespace = true;
+        let dir = temp_dir("accept");
+        let path = dir.join("a.txt");
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"a\r\nb\r\n", b"a\r\nb\r\n"),
+            (b"a\nb\n", b"a\nb\n"),
+            (b"a \r\nb\r\n", b"a\r\nb\r\n"),
+            (b"a\r\nb\n", b"a\nb\n"),
+            (b"a\rb\r", b"a\nb\n"),
+        ];
+        for &(raw, expected) in cases {
+            std::fs::write(&path, raw).unwrap();
+            convert_file(&path, &config).unwrap();
+            assert_eq!(read(&path).unwrap(), expected);
         }
-        Ok(())
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum Delim {
-    LF,
-    CR,
-    CRLF,
-}
+    #[test]
+    fn reject_mixed() {
+        let mut from_cr = config_to(Delim::LF);
+        from_cr.options.from = Some(Delim::CR);
+        from_cr.target_delim_set = kept_delims(Some(Delim::CR), &BTreeSet::new());
+        from_cr.target_delim_set.insert(Delim::LF);
+        let mut accept = config_to(Delim::LF);
+        accept.accepted = [Delim::LF, Delim::CRLF].iter().copied().collect();
 
-impl Delim {
-    fn write_to(self, w: &mut impl Write) -> Result<()> {
-        match self {
-            Delim::LF => w.write_all(b"\n")?,
-            Delim::CR => w.write_all(b"\r")?,
-            Delim::CRLF => w.write_all(b"\r\n")?,
+        let dir = temp_dir("reject-mixed");
+        let path = dir.join("a.txt");
+        // Converted without and with `--reject-mixed`.
+        let from_cr_cases: &[(&[u8], &[u8], &[u8])] = &[
+            (b"a\r\nb\r\n", b"a\r\nb\r\n", b"a\r\nb\r\n"),
+            (b"a\r\nb\n", b"a\r\nb\n", b"a\nb\n"),
+            (b"a\rb\r\n", b"a\nb\r\n", b"a\nb\n"),
+        ];
+        // Mixed files are converted by `--accept` anyway.
+        let accept_cases: &[(&[u8], &[u8], &[u8])] = &[
+            (b"a\r\nb\r\n", b"a\r\nb\r\n", b"a\r\nb\r\n"),
+            (b"a\r\nb\n", b"a\nb\n", b"a\nb\n"),
+        ];
+        for (config, cases) in &[(from_cr, from_cr_cases), (accept, accept_cases)] {
+            let rejecting = Config {
+                reject_mixed: true,
+                ..config.clone()
+            };
+            for &(raw, kept, rejected) in cases.iter() {
+                let scan = FileContents::from_bytes(raw).scan();
+                assert_eq!(rejecting.needs_conversion(&scan), raw != rejected);
+                for &(config, expected) in &[(config, kept), (&rejecting, rejected)] {
+                    std::fs::write(&path, raw).unwrap();
+                    convert_file(&path, config).unwrap();
+                    assert_eq!(read(&path).unwrap(), expected, "{:?}", raw);
+                }
+            }
         }
-        Ok(())
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-}
 
-impl fmt::Display for Delim {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Delim::LF => write!(f, "LF"),
-            Delim::CR => write!(f, "CR"),
-            Delim::CRLF => write!(f, "CRLF"),
+    #[test]
+    fn to_bytes_twice() {
+        let dir = temp_dir("to-bytes");
+        let path = dir.join("a.txt");
+        let mut config = config_to(Delim::LF);
+        config.target_delim_set = BTreeSet::new();
+        let cases: &[(&[u8], &[u8], &[u8])] = &[
+            (b"\n\r", b"a\nb\n", b"a\n\rb\n\r"),
+            (b"\n\r", b"a\r\nb\rc", b"a\n\rb\n\rc"),
+            (b"\r\n\r\n", b"a\r\nb\n", b"a\r\n\r\nb\r\n\r\n"),
+            (b"\r", b"a\nb\r\n", b"a\rb\r"),
+            (b"\x1e", b"a\nb\n", b"a\x1eb\x1e"),
+        ];
+        for &(bytes, raw, expected) in cases {
+            config.options.delim_bytes = Some(bytes.to_vec());
+            std::fs::write(&path, raw).unwrap();
+            let outcome = convert_file(&path, &config).unwrap();
+            assert!(matches!(outcome, Outcome::NeedsConversion(..)), "{:?}", raw);
+            assert_eq!(read(&path).unwrap(), expected, "{:?}", raw);
+
+            // The written bytes are taken as target delimiters, so nothing changes again.
+            let outcome = convert_file(&path, &config).unwrap();
+            assert!(matches!(outcome, Outcome::Correct(_)), "{:?}", raw);
+            assert_eq!(read(&path).unwrap(), expected, "{:?}", raw);
+        }
+
+        // Only whole occurrences are kept, and others are converted.
+        config.options.delim_bytes = Some(b"\n\r".to_vec());
+        std::fs::write(&path, b"a\n\r\nb\r").unwrap();
+        convert_file(&path, &config).unwrap();
+        assert_eq!(read(&path).unwrap(), b"a\n\r\n\rb\n\r");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tmp_file_names() {
+        assert_eq!(tmp_file_base("a.txt.tmp0", ".tmp"), Some("a.txt"));
+        assert_eq!(tmp_file_base("a.txt.tmp12", ".tmp"), Some("a.txt"));
+        assert_eq!(tmp_file_base("a.txt.tmp", ".tmp"), None);
+        assert_eq!(tmp_file_base("a.txt.tmpx", ".tmp"), None);
+        assert_eq!(tmp_file_base("a.txt.tmp0.bak", ".tmp"), None);
+        assert_eq!(tmp_file_base(".tmp0", ".tmp"), None);
+        assert_eq!(tmp_file_base("a.txt~fcrlf3", "~fcrlf"), Some("a.txt"));
+        assert_eq!(tmp_file_base("a.txt.tmp3", "~fcrlf"), None);
+    }
+
+    #[test]
+    fn clean_leftover_tmp_files() {
+        let dir = temp_dir("clean");
+        for name in &[
+            "a.txt",
+            "a.txt.tmp0",
+            "a.txt.tmp3",
+            "a.txt.tmpx",
+            "a.txt.tmp",
+            "b.txt.tmp0",
+        ] {
+            std::fs::write(dir.join(name), "a").unwrap();
+        }
+
+        let code = clean_tmp_files(&[Input::File(dir.join("a.txt"))], &config_to(Delim::LF));
+        assert_eq!(code, 0);
+        let mut names: Vec<String> = read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.txt", "a.txt.tmp", "a.txt.tmpx", "b.txt.tmp0"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skip_marked_file() {
+        let dir = temp_dir("marker");
+        let path = dir.join("a.rs");
+        let mut config = config_to(Delim::LF);
+        config.skip_marker = Some("DO NOT EDIT".to_string());
+
+        std::fs::write(&path, "// DO NOT EDIT\r\nfn main() {}\r\n").unwrap();
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::Marked));
+        assert_eq!(read(&path).unwrap(), b"// DO NOT EDIT\r\nfn main() {}\r\n");
+
+        std::fs::write(&path, b"\xFF\xFED\0O\0 \0N\0O\0T\0 \0E\0D\0I\0T\0\r\0\n\0").unwrap();
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::Marked));
+
+        std::fs::write(&path, "// Edit freely\r\n").unwrap();
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+        assert_eq!(read(&path).unwrap(), b"// Edit freely\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_over_keeps_inode() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = temp_dir("copy-over");
+        let (src, dst) = (dir.join("a.txt.tmp0"), dir.join("a.txt"));
+        std::fs::write(&src, "a\n").unwrap();
+        std::fs::write(&dst, "a\r\nb\r\n").unwrap();
+        std::fs::set_permissions(&dst, std::fs::Permissions::from_mode(0o4755)).unwrap();
+        let before = metadata(&dst).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+
+        copy_over(&src, &dst, Some(mtime)).unwrap();
+        assert_eq!(read(&dst).unwrap(), b"a\n");
+        let after = metadata(&dst).unwrap();
+        assert_eq!(after.ino(), before.ino());
+        assert_eq!(after.permissions().mode() & 0o7777, 0o4755);
+        assert_eq!(after.modified().unwrap(), mtime);
+        assert!(!src.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copy_over_creates_missing() {
+        let dir = temp_dir("copy-over-missing");
+        let (src, dst) = (dir.join("a.txt.tmp0"), dir.join("b.txt"));
+        std::fs::write(&src, "a\n").unwrap();
+
+        copy_over(&src, &dst, None).unwrap();
+        assert_eq!(read(&dst).unwrap(), b"a\n");
+        assert!(!src.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inplace_rewrite_hard_links() {
+        use std::fs::hard_link;
+
+        let dir = temp_dir("hard-link");
+        let (path, link) = (dir.join("a.txt"), dir.join("b.txt"));
+        let mut config = config_to(Delim::LF);
+
+        std::fs::write(&path, "a\r\n").unwrap();
+        hard_link(&path, &link).unwrap();
+        convert_file(&path, &config).unwrap();
+        assert_eq!(read(&link).unwrap(), b"a\r\n");
+
+        std::fs::remove_file(&link).unwrap();
+        std::fs::write(&path, "a\r\n").unwrap();
+        hard_link(&path, &link).unwrap();
+        config.inplace_rewrite = true;
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+        assert_eq!(read(&path).unwrap(), b"a\n");
+        assert_eq!(read(&link).unwrap(), b"a\n");
+        assert_eq!(read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keep_special_mode_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("mode");
+        let path = dir.join("a.sh");
+        for &mode in &[0o4755, 0o2755, 0o1755, 0o6750] {
+            std::fs::write(&path, "echo a\r\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+
+            let outcome = convert_file(&path, &config_to(Delim::LF)).unwrap();
+            assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+            assert_eq!(read(&path).unwrap(), b"echo a\n");
+            let converted = metadata(&path).unwrap().permissions().mode() & 0o7777;
+            assert_eq!(converted, mode, "{:o} became {:o}", mode, converted);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_writable_leaves_no_files() {
+        let dir = temp_dir("writable");
+        let path = dir.join("a.txt");
+        std::fs::write(&path, "a\r\n").unwrap();
+        let mut config = config_to(Delim::LF);
+        config.do_covert = false;
+        config.check_writable = true;
+
+        let outcome = convert_file(&path, &config).unwrap();
+        assert!(matches!(outcome, Outcome::NeedsConversion(..)));
+        assert_eq!(read(&path).unwrap(), b"a\r\n");
+        assert_eq!(read_dir(&dir).unwrap().count(), 1);
+
+        config.temp_dir = Some(dir.join("missing"));
+        let err = convert_file(&path, &config).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("can't be replaced"),
+            "{:#}",
+            err
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify() {
+        let dir = temp_dir("verify");
+        let path = dir.join("a.txt");
+        std::fs::write(&path, "a\r\nb\n").unwrap();
+
+        let mut config = config_to(Delim::LF);
+        config.verify = true;
+        let verify = config.verifier(Delim::LF, Encoding::Utf8, false).unwrap();
+        let err = verify.check(&path).unwrap_err();
+        assert_eq!(err.to_string(), "converted file contains LF, CRLF");
+
+        convert_file(&path, &config).unwrap();
+        verify.check(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Creates an empty directory for a test named `name`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fcrlf-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Config converting files to `target` without other options.
+    fn config_to(target: Delim) -> Config {
+        Config {
+            do_covert: true,
+            dry_run: false,
+            show_diff: false,
+            target_delim: target,
+            target_delim_set: [target].iter().copied().collect(),
+            auto_to: false,
+            accepted: BTreeSet::new(),
+            reject_mixed: false,
+            preserve_mtime: false,
+            inplace_rewrite: false,
+            check_unchanged: false,
+            max_file_size: None,
+            skip_marker: None,
+            verify: false,
+            process_binary: false,
+            format: Format::Text,
+            backup: None,
+            temp_dir: None,
+            out_dir: None,
+            temp_suffix: ".tmp".to_string(),
+            transaction: None,
+            interactive: None,
+            histogram: None,
+            gzip: false,
+            zip: None,
+            encoding: None,
+            options: Options::default(),
+            split: SplitOptions::default(),
+            stdin_name: STDIN_PATTERN.to_string(),
+            line_buffered: false,
+            color: ColorChoice::Never,
+            require_final_newline: false,
+            counts: false,
+            max_offenders: None,
+            first_offset: false,
+            check_writable: false,
+            print0: false,
+            mmap: false,
+            warn_mixed: false,
+            verbose: 0,
+            quiet: true,
+            full_scan: false,
         }
     }
 }
 
+/// Formats a number of files.
 #[derive(Debug)]
-struct DelimSetFmt<'a>(&'a BTreeSet<Delim>);
+struct FilesFmt(usize);
 
-impl<'a> fmt::Display for DelimSetFmt<'a> {
+impl fmt::Display for FilesFmt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.0.is_empty() {
-            write!(f, "NO_DELIM")?;
+        if self.0 == 1 {
+            write!(f, "1 file")
         } else {
-            for (i, v) in self.0.iter().enumerate() {
-                if i == 0 {
-                    write!(f, "{}", v)?;
-                } else {
-                    write!(f, ", {}", v)?;
-                }
-            }
+            write!(f, "{} files", self.0)
         }
+    }
+}
 
-        Ok(())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self, is_tty: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_tty,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Wraps a value in ANSI color escapes, if enabled.
+#[derive(Debug)]
+struct Paint<T> {
+    code: Option<&'static str>,
+    value: T,
+}
 
-    #[test]
-    fn parse_file() {
-        let raw = b"abc\r\ndef\nghi\rj";
-        let parsed = FileContents::from_bytes(raw);
+const ANSI_RED: &str = "31";
+const ANSI_GREEN: &str = "32";
+const ANSI_YELLOW: &str = "33";
 
-        assert_eq!(
-            parsed,
-            FileContents {
-                lines: vec![
-                    Line {
-                        text: b"abc".to_vec(),
-                        line_end: Some(Delim::CRLF)
-                    },
-                    Line {
-                        text: b"def".to_vec(),
-                        line_end: Some(Delim::LF)
-                    },
-                    Line {
-                        text: b"ghi".to_vec(),
-                        line_end: Some(Delim::CR)
-                    },
-                    Line {
-                        text: b"j".to_vec(),
-                        line_end: None
-                    },
-                ]
-            }
-        );
+impl<T: fmt::Display> Paint<T> {
+    fn new(enabled: bool, code: &'static str, value: T) -> Paint<T> {
+        Paint {
+            code: Some(code).filter(|_| enabled),
+            value,
+        }
     }
+}
 
-    #[test]
-    fn parse_write_round_trip() {
-        let raw = b"abc\r\ndef\nghi\rj";
-        let parsed = FileContents::from_bytes(raw);
-        let mut written = Vec::<u8>::new();
-        parsed.write_to(&mut written, Delim::CRLF).unwrap();
-        assert_eq!(&written, b"abc\r\ndef\r\nghi\r\nj");
+impl<T: fmt::Display> fmt::Display for Paint<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "\x1b[{}m{}\x1b[0m", code, self.value),
+            None => write!(f, "{}", self.value),
+        }
     }
 }
 
+/// Displays a path as `display_path` returns.
 #[derive(Debug)]
 struct PathFmt<'a>(&'a Path);
 
 impl<'a> fmt::Display for PathFmt<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Some(s) = self.0.to_str() {
+        let path = display_path(self.0);
+        if let Some(s) = path.to_str() {
             write!(f, "{}", s)
         } else {
-            write!(f, "{:?}", self.0)
+            write!(f, "{:?}", path)
+        }
+    }
+}
+
+/// Returns `path` as shown to users, which is relative to the directory of `--relative-to` if given.
+/// Files are always accessed by their original paths.
+fn display_path(path: &Path) -> Cow<'_, Path> {
+    match DISPLAY_BASE.get() {
+        Some((base, cwd)) => Cow::Owned(display_relative(path, base, cwd)),
+        None => Cow::Borrowed(path),
+    }
+}
+
+fn display_relative(path: &Path, base: &Path, cwd: &Path) -> PathBuf {
+    // `..` is resolved lexically, as the path may not exist anymore.
+    let mut absolute = PathBuf::new();
+    for c in cwd.join(path).components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            c => absolute.push(c),
         }
     }
+    let relative = relative_to(&absolute, base);
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+/// Makes absolute `path` relative to `base`, going up with `..` if needed.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return path.to_path_buf();
+    }
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+    relative
 }
@@ -0,0 +1,127 @@
+//! Confirmation of each file before converting it, for `--interactive`.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, stderr, stdin, BufRead, Write};
+use std::sync::Mutex;
+
+/// Answer to a prompt for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Yes,
+    No,
+    /// Converts this and all the rest without asking.
+    All,
+    /// Converts nothing more, and aborts the run.
+    Quit,
+}
+
+impl Answer {
+    /// Parses an answer, or returns `None` to ask again. An empty answer is no, to be safe.
+    fn parse(s: &str) -> Option<Answer> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => Some(Answer::Yes),
+            "" | "n" | "no" => Some(Answer::No),
+            "a" | "all" => Some(Answer::All),
+            "q" | "quit" => Some(Answer::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Prompts shared by all files, which are asked one at a time.
+///
+/// Once answered all or quit, the same answer is returned without asking again.
+#[derive(Debug, Default)]
+pub struct Prompt {
+    decided: Mutex<Option<Answer>>,
+}
+
+impl Prompt {
+    /// Asks whether to convert a file described by `summary`, on stderr and stdin.
+    pub fn confirm(&self, summary: &dyn fmt::Display) -> io::Result<Answer> {
+        self.confirm_with(summary, &mut stdin().lock(), &mut stderr())
+    }
+
+    /// The end of `input` is taken as quit.
+    fn confirm_with(
+        &self,
+        summary: &dyn fmt::Display,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> io::Result<Answer> {
+        let mut decided = self.decided.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(answer) = *decided {
+            return Ok(answer);
+        }
+
+        loop {
+            write!(output, "convert {}? [y/n/a/q] ", summary)?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                *decided = Some(Answer::Quit);
+                return Ok(Answer::Quit);
+            }
+            match Answer::parse(&line) {
+                Some(answer @ (Answer::All | Answer::Quit)) => {
+                    *decided = Some(answer);
+                    return Ok(answer);
+                }
+                Some(answer) => return Ok(answer),
+                None => writeln!(output, "answer y(es), n(o), a(ll) or q(uit)")?,
+            }
+        }
+    }
+}
+
+/// Error of a run aborted by answering quit, which stops it even without `--fail-fast`.
+#[derive(Debug)]
+pub struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "aborted by user")
+    }
+}
+
+impl Error for Aborted {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers() {
+        let prompt = Prompt::default();
+        let mut output = Vec::new();
+        let mut input = &b"Y\n\nwhat\nn\na\n"[..];
+        let mut ask = || {
+            prompt
+                .confirm_with(&"a.txt", &mut input, &mut output)
+                .unwrap()
+        };
+        assert_eq!(ask(), Answer::Yes);
+        assert_eq!(ask(), Answer::No);
+        assert_eq!(ask(), Answer::No);
+        assert_eq!(ask(), Answer::All);
+        // Remembered, without reading more.
+        assert_eq!(ask(), Answer::All);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("convert a.txt? [y/n/a/q] ").count(), 5);
+        assert_eq!(output.matches("answer y(es)").count(), 1);
+
+        let prompt = Prompt::default();
+        let mut output = Vec::new();
+        let mut input = &b"y\n"[..];
+        let mut ask = || {
+            prompt
+                .confirm_with(&"a.txt", &mut input, &mut output)
+                .unwrap()
+        };
+        assert_eq!(ask(), Answer::Yes);
+        assert_eq!(ask(), Answer::Quit);
+        assert_eq!(ask(), Answer::Quit);
+    }
+}
@@ -0,0 +1,190 @@
+//! Log of every processed file, written by `--report`, or to stdout by `--format jsonl`.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use fcrlf::Delim;
+
+use crate::{display_path, DelimSetFmt, Input, Outcome, PathFmt};
+
+#[derive(Debug, Serialize)]
+pub struct ReportEntry<'a> {
+    path: String,
+    /// `None` for skipped and failed files, whose delimiters are unknown.
+    delimiters: Option<&'a BTreeSet<Delim>>,
+    /// Delimiters converted or to be converted, present only for files needing conversion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    converted_from: Option<&'a BTreeSet<Delim>>,
+    /// `None` for files skipped without a target.
+    target: Option<String>,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+impl<'a> ReportEntry<'a> {
+    /// Makes the entry of `input`. `converted` tells whether files needing conversion were converted.
+    pub fn new(
+        input: &Input,
+        outcome: &'a Outcome,
+        target: Option<String>,
+        converted: bool,
+        stdin_name: &str,
+    ) -> ReportEntry<'a> {
+        let path = match input {
+            Input::Stdin => stdin_name.to_string(),
+            Input::File(p) => display_path(p).to_string_lossy().to_string(),
+        };
+        let (delimiters, status) = match outcome {
+            Outcome::Correct(types) => (Some(types), "correct"),
+            Outcome::NeedsConversion(types, _) if converted => (Some(types), "converted"),
+            Outcome::NeedsConversion(types, _) => (Some(types), "needs_conversion"),
+            Outcome::MissingFinalNewline(types) => (Some(types), "missing_final_newline"),
+            Outcome::Binary => (None, "skipped_binary"),
+            Outcome::Symlink => (None, "skipped_symlink"),
+            Outcome::TooLarge => (None, "skipped_too_large"),
+            Outcome::NoTarget => (None, "skipped_no_target"),
+            Outcome::Marked => (None, "skipped_marker"),
+            Outcome::Declined => (None, "skipped_declined"),
+            Outcome::Failed(_) => (None, "failed"),
+        };
+        let converted_from = match outcome {
+            Outcome::NeedsConversion(_, from) => Some(from),
+            _ => None,
+        };
+        let error = match outcome {
+            Outcome::Failed(message) => Some(message.as_str()),
+            _ => None,
+        };
+        ReportEntry {
+            path,
+            delimiters,
+            converted_from,
+            target,
+            status,
+            error,
+        }
+    }
+}
+
+/// Writes `entries` to `path`, as CSV if its extension is `csv` and as JSON otherwise.
+pub fn write_report(path: &Path, entries: &[ReportEntry]) -> Result<()> {
+    let write = || -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        if path.extension().is_some_and(|ext| ext == "csv") {
+            write_csv(&mut w, entries)?;
+        } else {
+            serde_json::to_writer_pretty(&mut w, entries)?;
+            writeln!(w)?;
+        }
+        w.flush()?;
+        Ok(())
+    };
+    write().with_context(|| format!("writing report to {}", PathFmt(path)))
+}
+
+/// Writes `entry` to stdout as a line of JSON, for `--format jsonl`.
+///
+/// The line is written at once while stdout is locked, so lines of files processed in parallel don't mix.
+pub fn write_json_line(entry: &ReportEntry) -> Result<()> {
+    let line = json_line(entry)?;
+    let stdout = stdout();
+    let mut w = stdout.lock();
+    w.write_all(&line)
+        .and_then(|_| w.flush())
+        .context("writing JSON to stdout")
+}
+
+fn json_line(entry: &ReportEntry) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn write_csv(w: &mut impl Write, entries: &[ReportEntry]) -> Result<()> {
+    writeln!(w, "path,delimiters,converted_from,target,status,error")?;
+    for entry in entries {
+        let delimiters = entry
+            .delimiters
+            .map(|types| DelimSetFmt(types).to_string())
+            .unwrap_or_default();
+        let converted_from = entry
+            .converted_from
+            .map(|types| DelimSetFmt(types).to_string())
+            .unwrap_or_default();
+        writeln!(
+            w,
+            "{},{},{},{},{},{}",
+            csv_field(&entry.path),
+            csv_field(&delimiters),
+            csv_field(&converted_from),
+            csv_field(entry.target.as_deref().unwrap_or_default()),
+            entry.status,
+            csv_field(entry.error.unwrap_or_default()),
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field if it contains commas, quotes or line breaks.
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn entries() {
+        let mixed: BTreeSet<Delim> = [Delim::LF, Delim::CRLF].iter().copied().collect();
+        let outcome = Outcome::NeedsConversion(mixed, [Delim::CRLF].iter().copied().collect());
+        let input = Input::File(PathBuf::from("a,b.txt"));
+        let failed = Outcome::Failed("reading \"c.txt\": not found".to_string());
+        let entries = vec![
+            ReportEntry::new(&input, &outcome, Some("LF".to_string()), true, "-"),
+            ReportEntry::new(
+                &Input::Stdin,
+                &failed,
+                Some("LF".to_string()),
+                true,
+                "c.txt",
+            ),
+            ReportEntry::new(&input, &Outcome::NoTarget, None, true, "-"),
+        ];
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &entries).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "path,delimiters,converted_from,target,status,error\n\
+             \"a,b.txt\",\"LF, CRLF\",CRLF,LF,converted,\n\
+             c.txt,,,LF,failed,\"reading \"\"c.txt\"\": not found\"\n\
+             \"a,b.txt\",,,,skipped_no_target,\n"
+        );
+
+        assert_eq!(
+            serde_json::to_string(&entries[0]).unwrap(),
+            r#"{"path":"a,b.txt","delimiters":["LF","CRLF"],"converted_from":["CRLF"],"target":"LF","status":"converted"}"#
+        );
+        let entry = ReportEntry::new(&input, &outcome, Some("LF".to_string()), false, "-");
+        assert_eq!(entry.status, "needs_conversion");
+
+        let line = json_line(&entries[1]).unwrap();
+        assert_eq!(
+            String::from_utf8(line).unwrap(),
+            "{\"path\":\"c.txt\",\"delimiters\":null,\"target\":\"LF\",\"status\":\"failed\",\
+             \"error\":\"reading \\\"c.txt\\\": not found\"}\n"
+        );
+    }
+}
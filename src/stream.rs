@@ -0,0 +1,548 @@
+use std::io::{self, BufRead, Write};
+use std::mem::swap;
+
+use memchr::memchr2;
+
+use crate::contents::{
+    has_tabs, Ending, FileContents, FinalNewline, FinalNewlineWriter, Line, Options, RegionTracker,
+    Scan,
+};
+use crate::delim::{Delim, Encoding};
+use crate::error::Result;
+use crate::UTF8_BOM;
+
+/// Converts line delimiters of `r` to `delim` and writes them to `w`, line by line.
+pub fn convert_stream(
+    r: impl BufRead,
+    w: &mut impl Write,
+    delim: Delim,
+    options: &Options,
+) -> Result<()> {
+    convert_stream_with(r, w, delim, options, &mut Scan::default(), false)
+}
+
+/// Converts like `convert_stream`, also collecting properties of `r` into `scan` as `scan_stream` does.
+///
+/// Each line is written as soon as it's read, except that `FinalNewline::Remove` needs the next one.
+/// With `flush_lines`, `w` is also flushed after each line, for interactive pipelines.
+///
+/// With `options.delim_bytes`, all of `r` is read first, because its occurrences already in `r`
+/// may span several lines.
+pub fn convert_stream_with(
+    mut r: impl BufRead,
+    w: &mut impl Write,
+    delim: Delim,
+    options: &Options,
+    scan: &mut Scan,
+    flush_lines: bool,
+) -> Result<()> {
+    if options.delim_bytes.is_some() {
+        let mut raw = Vec::new();
+        r.read_to_end(&mut raw)?;
+        let contents = FileContents::from_bytes_with(&raw, Encoding::Utf8);
+        *scan = contents.scan_with(options);
+        return contents.write_to(w, delim, options);
+    }
+
+    let mut lines = LineReader::new(r);
+    let mut line = Line::new();
+    let mut next = Line::new();
+
+    if !lines.read_line(&mut line)? {
+        return Ok(());
+    }
+    if line.text.starts_with(UTF8_BOM) {
+        line.text.drain(..UTF8_BOM.len());
+        scan.has_bom = true;
+        if !options.strip_bom {
+            w.write_all(UTF8_BOM)?;
+        }
+    }
+
+    if options.final_newline_only {
+        let mut writer = FinalNewlineWriter::default();
+        loop {
+            writer.write_line(w, &line, options, Encoding::Utf8)?;
+            after_line(w, &line, options, scan, flush_lines)?;
+            if !lines.read_line(&mut line)? {
+                return writer.finish(w, delim, options, Encoding::Utf8);
+            }
+        }
+    }
+
+    let lookahead = options.final_newline == FinalNewline::Remove;
+    let mut tracker = options
+        .regions
+        .as_ref()
+        .map(|regions| RegionTracker::new(regions, Encoding::Utf8));
+    loop {
+        let keep = tracker.as_mut().is_some_and(|t| t.keeps(&line));
+        if !lookahead {
+            options.write_line(w, &line, None, delim, Encoding::Utf8, keep)?;
+            after_line(w, &line, options, scan, flush_lines)?;
+            if !lines.read_line(&mut line)? {
+                return Ok(());
+            }
+            continue;
+        }
+
+        let has_next = lines.read_line(&mut next)?;
+        let next_line = Some(&next).filter(|_| has_next);
+        options.write_line(w, &line, next_line, delim, Encoding::Utf8, keep)?;
+        after_line(w, &line, options, scan, flush_lines)?;
+        if !has_next {
+            return Ok(());
+        }
+        swap(&mut line, &mut next);
+    }
+}
+
+/// Records `line` written by `convert_stream_with` into `scan`, and flushes `w` if `flush`.
+fn after_line(
+    w: &mut impl Write,
+    line: &Line,
+    options: &Options,
+    scan: &mut Scan,
+    flush: bool,
+) -> io::Result<()> {
+    if let Some(d) = line.line_end {
+        scan.delim_types.insert(d);
+        *scan.delim_counts.entry(d).or_insert(0) += 1;
+        scan.ending = Ending::Delim;
+    } else if !line.text.is_empty() {
+        scan.ending = Ending::Text;
+    }
+    if matches!(line.text.last(), Some(b' ') | Some(b'\t')) {
+        scan.trailing_whitespace = true;
+    }
+    if options.replace_tabs.is_some() && has_tabs(&line.text, options.all_tabs, Encoding::Utf8) {
+        scan.tabs = true;
+    }
+    if flush {
+        w.flush()?;
+    }
+    Ok(())
+}
+
+/// Collects delimiter types and trailing whitespace in UTF-8 `r` into `scan`,
+/// without keeping its contents.
+/// Reading stops early once `is_done` returns true for `scan`.
+pub fn scan_stream(
+    r: impl BufRead,
+    scan: &mut Scan,
+    is_done: impl Fn(&Scan) -> bool,
+) -> io::Result<()> {
+    let mut lines = LineReader::new(r);
+
+    loop {
+        let mut last_byte = None;
+        let line_end =
+            match lines.next_line(|chunk| last_byte = chunk.last().copied().or(last_byte))? {
+                Some(line_end) => line_end,
+                None => return Ok(()),
+            };
+
+        if let Some(d) = line_end {
+            scan.delim_types.insert(d);
+            *scan.delim_counts.entry(d).or_insert(0) += 1;
+        }
+        if matches!(last_byte, Some(b' ') | Some(b'\t')) {
+            scan.trailing_whitespace = true;
+        }
+        if is_done(scan) {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads lines one by one, splitting them in the same way as `FileContents::from_bytes`.
+/// Unicode separators are not recognized.
+#[derive(Debug)]
+pub struct LineReader<R> {
+    inner: R,
+    finished: bool,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub fn new(inner: R) -> LineReader<R> {
+        LineReader {
+            inner,
+            finished: false,
+        }
+    }
+
+    /// Reads the next line into `line`.
+    /// Returns `false` when there are no more lines.
+    pub fn read_line(&mut self, line: &mut Line) -> io::Result<bool> {
+        line.text.clear();
+        let text = &mut line.text;
+        match self.next_line(|chunk| text.extend_from_slice(chunk))? {
+            Some(line_end) => {
+                line.line_end = line_end;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Reads the next line, passing its text to `on_text` chunk by chunk.
+    /// Returns the line's delimiter, or `None` when there are no more lines.
+    ///
+    /// As with `FileContents::from_bytes`, the last line has no delimiter and may be empty.
+    pub fn next_line(
+        &mut self,
+        mut on_text: impl FnMut(&[u8]),
+    ) -> io::Result<Option<Option<Delim>>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        loop {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() {
+                self.finished = true;
+                return Ok(Some(None));
+            }
+
+            match memchr2(b'\r', b'\n', buf) {
+                Some(i) => {
+                    on_text(&buf[..i]);
+                    let b = buf[i];
+                    self.inner.consume(i + 1);
+
+                    if b == b'\n' {
+                        return Ok(Some(Some(Delim::LF)));
+                    }
+                    // CR may be split from following LF at the buffer boundary.
+                    if self.inner.fill_buf()?.first() == Some(&b'\n') {
+                        self.inner.consume(1);
+                        return Ok(Some(Some(Delim::CRLF)));
+                    }
+                    return Ok(Some(Some(Delim::CR)));
+                }
+                None => {
+                    on_text(buf);
+                    let n = buf.len();
+                    self.inner.consume(n);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::io::BufReader;
+
+    use super::*;
+    use crate::contents::FileContents;
+
+    #[test]
+    fn parse_write_utf8_bom() {
+        let raw = b"\xEF\xBB\xBFabc\r\ndef";
+        let parsed = FileContents::from_bytes(raw);
+        assert!(parsed.bom);
+        assert_eq!(parsed.lines[0].text, b"abc");
+
+        let mut written = Vec::<u8>::new();
+        parsed
+            .write_to(&mut written, Delim::LF, &Options::default())
+            .unwrap();
+        assert_eq!(&written, b"\xEF\xBB\xBFabc\ndef");
+
+        let strip = Options {
+            strip_bom: true,
+            ..Options::default()
+        };
+        let mut written = Vec::<u8>::new();
+        parsed.write_to(&mut written, Delim::LF, &strip).unwrap();
+        assert_eq!(&written, b"abc\ndef");
+
+        let mut written = Vec::<u8>::new();
+        convert_stream(&raw[..], &mut written, Delim::LF, &strip).unwrap();
+        assert_eq!(&written, b"abc\ndef");
+    }
+
+    #[test]
+    fn convert_stream_same_as_write_to() {
+        let raw = b"abc\r\ndef\nghi\rj\r";
+        for &delim in &Delim::ALL {
+            let mut expected = Vec::<u8>::new();
+            FileContents::from_bytes(raw)
+                .write_to(&mut expected, delim, &Options::default())
+                .unwrap();
+
+            let mut written = Vec::<u8>::new();
+            convert_stream(&raw[..], &mut written, delim, &Options::default()).unwrap();
+            assert_eq!(written, expected);
+        }
+    }
+
+    #[test]
+    fn convert_stream_regions() {
+        let raw = b"a\r\n// crlf-start\r\nb\r\n// crlf-end\r\nc\r\n";
+        let options = Options {
+            regions: Some(crate::Regions {
+                start: "crlf-start".to_string(),
+                end: "crlf-end".to_string(),
+            }),
+            ..Options::default()
+        };
+        let mut expected = Vec::<u8>::new();
+        FileContents::from_bytes(raw)
+            .write_to(&mut expected, Delim::LF, &options)
+            .unwrap();
+
+        let mut written = Vec::<u8>::new();
+        convert_stream(&raw[..], &mut written, Delim::LF, &options).unwrap();
+        assert_eq!(written, expected);
+        assert_eq!(
+            &written[..],
+            &b"a\n// crlf-start\r\nb\r\n// crlf-end\nc\n"[..]
+        );
+    }
+
+    #[test]
+    fn convert_stream_scanning() {
+        let tabs = Options {
+            replace_tabs: Some(2),
+            ..Options::default()
+        };
+        for raw in &[
+            &b"\xEF\xBB\xBFa \r\nb\n\rc"[..],
+            b"a\r\n",
+            b"",
+            b"\n\n",
+            b"a\n\tb",
+        ] {
+            for options in &[Options::default(), tabs.clone()] {
+                let mut scan = Scan::default();
+                let mut written = Vec::<u8>::new();
+                convert_stream_with(&raw[..], &mut written, Delim::LF, options, &mut scan, true)
+                    .unwrap();
+                let contents = FileContents::from_bytes(raw);
+                assert_eq!(scan, contents.scan_with(options));
+
+                let mut expected = Vec::<u8>::new();
+                contents
+                    .write_to(&mut expected, Delim::LF, options)
+                    .unwrap();
+                assert_eq!(written, expected);
+            }
+        }
+    }
+
+    /// Records what is written until each flush, to check that lines are not held back.
+    #[derive(Default)]
+    struct Flushes {
+        pending: Vec<u8>,
+        flushed: Vec<Vec<u8>>,
+    }
+
+    impl Write for Flushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.pending.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed.push(std::mem::take(&mut self.pending));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn convert_stream_flush_lines() {
+        let mut w = Flushes::default();
+        convert_stream_with(
+            &b"a\r\nb\rc"[..],
+            &mut w,
+            Delim::LF,
+            &Options::default(),
+            &mut Scan::default(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(w.flushed, [&b"a\n"[..], b"b\n", b"c"]);
+    }
+
+    #[test]
+    fn convert_stream_final_newline_only() {
+        let options = Options {
+            final_newline_only: true,
+            ..Options::default()
+        };
+        for raw in &[&b"a\r\n\nb\r\n\r\n"[..], b"\n\na\rb", b"\r\n", b""] {
+            let mut expected = Vec::<u8>::new();
+            FileContents::from_bytes(raw)
+                .write_to(&mut expected, Delim::LF, &options)
+                .unwrap();
+
+            let mut written = Vec::<u8>::new();
+            convert_stream(&raw[..], &mut written, Delim::LF, &options).unwrap();
+            assert_eq!(written, expected);
+        }
+    }
+
+    #[test]
+    fn final_newline() {
+        let cases: &[(&[u8], FinalNewline, &[u8])] = &[
+            (b"a\r\nb", FinalNewline::Ensure, b"a\nb\n"),
+            (b"a\r\nb\r\n", FinalNewline::Ensure, b"a\nb\n"),
+            (b"", FinalNewline::Ensure, b""),
+            (b"\xEF\xBB\xBF", FinalNewline::Ensure, b"\xEF\xBB\xBF"),
+            (b"a\r\nb\r\n", FinalNewline::Remove, b"a\nb"),
+            (b"a\r\n\r\n", FinalNewline::Remove, b"a\n"),
+            (b"a\r\nb", FinalNewline::Remove, b"a\nb"),
+        ];
+
+        for &(raw, final_newline, expected) in cases {
+            let options = Options {
+                final_newline,
+                ..Options::default()
+            };
+
+            let mut written = Vec::<u8>::new();
+            FileContents::from_bytes(raw)
+                .write_to(&mut written, Delim::LF, &options)
+                .unwrap();
+            assert_eq!(written, expected, "write_to {:?} {:?}", raw, final_newline);
+
+            let mut written = Vec::<u8>::new();
+            convert_stream(raw, &mut written, Delim::LF, &options).unwrap();
+            assert_eq!(
+                written, expected,
+                "convert_stream {:?} {:?}",
+                raw, final_newline
+            );
+        }
+    }
+
+    #[test]
+    fn convert_only_from() {
+        let options = Options {
+            from: Some(Delim::CR),
+            ..Options::default()
+        };
+
+        let raw = b"a\rb\r\nc\nd\r";
+        let mut written = Vec::<u8>::new();
+        FileContents::from_bytes(raw)
+            .write_to(&mut written, Delim::LF, &options)
+            .unwrap();
+        assert_eq!(&written, b"a\nb\r\nc\nd\n");
+
+        let mut streamed = Vec::<u8>::new();
+        convert_stream(&raw[..], &mut streamed, Delim::LF, &options).unwrap();
+        assert_eq!(streamed, written);
+    }
+
+    #[test]
+    fn convert_to_bytes() {
+        let options = Options {
+            delim_bytes: Some(b"\n\r".to_vec()),
+            final_newline: FinalNewline::Ensure,
+            ..Options::default()
+        };
+
+        let raw = b"a\r\nb\nc";
+        let mut written = Vec::<u8>::new();
+        FileContents::from_bytes(raw)
+            .write_to(&mut written, Delim::LF, &options)
+            .unwrap();
+        assert_eq!(&written, b"a\n\rb\n\rc\n\r");
+
+        let mut streamed = Vec::<u8>::new();
+        convert_stream(&raw[..], &mut streamed, Delim::LF, &options).unwrap();
+        assert_eq!(streamed, written);
+
+        let mut again = Vec::<u8>::new();
+        convert_stream(&written[..], &mut again, Delim::LF, &options).unwrap();
+        assert_eq!(again, written);
+
+        let options = Options {
+            from: Some(Delim::CRLF),
+            ..options
+        };
+        let mut written = Vec::<u8>::new();
+        convert_stream(&raw[..], &mut written, Delim::LF, &options).unwrap();
+        assert_eq!(&written, b"a\n\rb\nc\n\r");
+    }
+
+    #[test]
+    fn trim_trailing_whitespace() {
+        let options = Options {
+            trim_trailing_whitespace: true,
+            ..Options::default()
+        };
+
+        let raw = b"a \t\r\n b\t\n \r c ";
+        let mut written = Vec::<u8>::new();
+        FileContents::from_bytes(raw)
+            .write_to(&mut written, Delim::LF, &options)
+            .unwrap();
+        assert_eq!(&written, b"a\n b\n\n c");
+
+        let mut streamed = Vec::<u8>::new();
+        convert_stream(&raw[..], &mut streamed, Delim::LF, &options).unwrap();
+        assert_eq!(streamed, written);
+
+        let utf16 = b"\xFF\xFEa\0 \0\t\0\n\0 \x20";
+        let mut written = Vec::<u8>::new();
+        FileContents::from_bytes(utf16)
+            .write_to(&mut written, Delim::LF, &options)
+            .unwrap();
+        assert_eq!(&written, b"\xFF\xFEa\0\n\0 \x20");
+    }
+
+    #[test]
+    fn scan_stream_same_as_scan() {
+        for &raw in &[&b"abc\ndef\r\nghi\r"[..], b"abc \ndef", b"abc\ndef\t"] {
+            let expected = FileContents::from_bytes(raw).scan();
+
+            let mut scan = Scan {
+                delim_types: BTreeSet::new(),
+                delim_counts: BTreeMap::new(),
+                has_bom: expected.has_bom,
+                ending: expected.ending,
+                trailing_whitespace: false,
+                tabs: false,
+            };
+            scan_stream(raw, &mut scan, |_| false).unwrap();
+            assert_eq!(scan, expected, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn crlf_split_across_reads() {
+        for &raw in &[&b"a\r\nb\rc\r\n"[..], b"a\r", b"\r\r\n", b"a\r\n\r"] {
+            let expected = FileContents::from_bytes(raw);
+
+            let mut lines = LineReader::new(BufReader::with_capacity(1, raw));
+            let mut line = Line::new();
+            let mut read = Vec::new();
+            while lines.read_line(&mut line).unwrap() {
+                read.push(line.clone());
+            }
+            assert_eq!(read, expected.lines, "{:?}", raw);
+
+            let mut written = Vec::<u8>::new();
+            let r = BufReader::with_capacity(1, raw);
+            convert_stream(r, &mut written, Delim::LF, &Options::default()).unwrap();
+            let mut expected_written = Vec::<u8>::new();
+            expected
+                .write_to(&mut expected_written, Delim::LF, &Options::default())
+                .unwrap();
+            assert_eq!(written, expected_written, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn scan_stream_stops_early() {
+        let raw = b"abc\ndef\r\nghi\r";
+        let mut scan = FileContents::from_bytes(b"").scan();
+        scan_stream(&raw[..], &mut scan, |_| true).unwrap();
+        assert_eq!(scan.delim_types, [Delim::LF].iter().copied().collect());
+    }
+}
@@ -0,0 +1,120 @@
+//! Temporary files which don't outlive an interrupted or failed run.
+
+use std::collections::BTreeSet;
+use std::fs::remove_file;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::Mutex;
+
+use crate::{warn, PathFmt};
+
+/// Exit code of a run interrupted by Ctrl-C, following the shell convention for SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Temporary files which exist now, to be removed on interrupt.
+static LIVE: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Path of a temporary file, which is removed when dropped unless `release`d.
+///
+/// So early returns and panics don't leave it behind, and it's removed on Ctrl-C too.
+#[derive(Debug)]
+pub struct TempPath {
+    path: PathBuf,
+    released: bool,
+}
+
+impl TempPath {
+    /// Takes ownership of the created file at `path`.
+    pub fn new(path: PathBuf) -> TempPath {
+        live().insert(path.clone());
+        TempPath {
+            path,
+            released: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops removing the file, after it's renamed away or removed otherwise.
+    pub fn release(&mut self) {
+        if !self.released {
+            live().remove(&self.path);
+            self.released = true;
+        }
+    }
+
+    /// Removes the file now, returning the error unlike dropping.
+    pub fn remove(mut self) -> io::Result<()> {
+        self.release();
+        remove_file(&self.path)
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        self.release();
+        match remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => warn(format_args!(
+                "failed to remove temporary file {}: {}",
+                PathFmt(&self.path),
+                e
+            )),
+            _ => {}
+        }
+    }
+}
+
+/// Locks the set of live temporary files, even if a panicking thread held it.
+fn live() -> std::sync::MutexGuard<'static, BTreeSet<PathBuf>> {
+    LIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Makes Ctrl-C remove all live temporary files before exiting.
+///
+/// The lock is held until exit, so no more temporary files are created meanwhile.
+pub fn remove_on_interrupt() {
+    let res = ctrlc::set_handler(|| {
+        let live = live();
+        for path in live.iter() {
+            let _ = remove_file(path);
+        }
+        eprintln!("Interrupted");
+        exit(EXIT_INTERRUPTED);
+    });
+    if let Err(e) = res {
+        warn(format_args!("can't handle interrupts: {}", e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::write;
+
+    #[test]
+    fn removed_unless_released() {
+        let dir = temp_dir();
+        let dropped = dir.join(format!("fcrlf-temp-dropped-{}", std::process::id()));
+        write(&dropped, "a").unwrap();
+        drop(TempPath::new(dropped.clone()));
+        assert!(!dropped.exists());
+        assert!(!live().contains(&dropped));
+
+        let released = dir.join(format!("fcrlf-temp-released-{}", std::process::id()));
+        write(&released, "a").unwrap();
+        let mut temp = TempPath::new(released.clone());
+        assert!(live().contains(&released));
+        temp.release();
+        drop(temp);
+        assert!(released.exists());
+        assert!(!live().contains(&released));
+        remove_file(&released).unwrap();
+    }
+}